@@ -0,0 +1,473 @@
+use crate::{Point, Rect, Size};
+
+/// Pixel layout of an atlas page
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum AtlasFormat {
+    /// One byte of coverage per pixel
+    #[default]
+    Alpha,
+    /// Four bytes per pixel, straight (not premultiplied) RGBA
+    Rgba,
+}
+
+impl AtlasFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            AtlasFormat::Alpha => 1,
+            AtlasFormat::Rgba => 4,
+        }
+    }
+}
+
+/// A rectangle of texels in an atlas page
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRegion {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The smallest region containing both
+    ///
+    pub fn union(&self, other: &AtlasRegion) -> AtlasRegion {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        AtlasRegion {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// A texture upload the renderer has to make before drawing. The texel data is read from the
+/// page with `AtlasPage::region_data`.
+///
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AtlasUpdate {
+    pub page: usize,
+    /// The page was created since the last update, or made again in another format, and a
+    /// texture has to be created for it
+    pub new_page: bool,
+    pub region: AtlasRegion,
+}
+
+/// A row of the page that regions are packed into left to right
+///
+#[derive(Copy, Clone, Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32,
+}
+
+/// One square texture of the atlas. Regions are shelf packed, and never freed individually.
+///
+#[derive(Clone, Debug)]
+pub struct AtlasPage {
+    format: AtlasFormat,
+    size: u32,
+    data: Vec<u8>,
+    shelves: Vec<Shelf>,
+    dirty: AtlasRegion,
+    new_page: bool,
+}
+
+impl AtlasPage {
+    fn new(format: AtlasFormat, size: u32) -> Self {
+        Self {
+            format,
+            size,
+            data: vec![0; (size * size) as usize * format.bytes_per_pixel()],
+            shelves: Vec::new(),
+            dirty: AtlasRegion::default(),
+            new_page: true,
+        }
+    }
+
+    pub fn format(&self) -> AtlasFormat {
+        self.format
+    }
+
+    /// Width and height of the page in texels
+    ///
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The texels of the whole page, row by row
+    ///
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The texels of `region`, tightly packed row by row
+    ///
+    pub fn region_data(&self, region: &AtlasRegion) -> Vec<u8> {
+        let bpp = self.format.bytes_per_pixel();
+        let mut out = Vec::with_capacity((region.width * region.height) as usize * bpp);
+        for row in region.y..region.y + region.height {
+            let start = (row * self.size + region.x) as usize * bpp;
+            out.extend_from_slice(&self.data[start..start + region.width as usize * bpp]);
+        }
+        out
+    }
+
+    /// Normalized texture coordinates of a region
+    ///
+    pub fn uv_rect(&self, region: &AtlasRegion) -> Rect {
+        let size = self.size as f32;
+        Rect::from_pos_and_size(
+            Point::from_xy(region.x as f32 / size, region.y as f32 / size),
+            Size::from_wh(region.width as f32 / size, region.height as f32 / size),
+        )
+    }
+
+    /// Find space for a `width` x `height` region, keeping `padding` texels clear on the right
+    /// and bottom. Shelves are reused when the region fits and does not waste too much height.
+    ///
+    fn allocate(&mut self, width: u32, height: u32, padding: u32) -> Option<AtlasRegion> {
+        let padded_w = width + padding;
+        let padded_h = height + padding;
+        if padded_w > self.size || padded_h > self.size {
+            return None;
+        }
+
+        let size = self.size;
+        let shelf = self
+            .shelves
+            .iter_mut()
+            .filter(|shelf| {
+                shelf.height >= padded_h
+                    && shelf.height <= padded_h + padded_h / 2
+                    && shelf.x + padded_w <= size
+            })
+            .min_by_key(|shelf| shelf.height);
+
+        let shelf = match shelf {
+            Some(shelf) => shelf,
+            None => {
+                let y = self
+                    .shelves
+                    .last()
+                    .map_or(0, |shelf| shelf.y + shelf.height);
+                if y + padded_h > self.size {
+                    return None;
+                }
+                self.shelves.push(Shelf {
+                    y,
+                    height: padded_h,
+                    x: 0,
+                });
+                self.shelves.last_mut().unwrap()
+            }
+        };
+
+        let region = AtlasRegion {
+            x: shelf.x,
+            y: shelf.y,
+            width,
+            height,
+        };
+        shelf.x += padded_w;
+        Some(region)
+    }
+
+    /// Copy tightly packed texels into a region and mark it for upload
+    ///
+    fn write(&mut self, region: &AtlasRegion, texels: &[u8]) {
+        let bpp = self.format.bytes_per_pixel();
+        let row_len = region.width as usize * bpp;
+        for (i, row) in texels.chunks_exact(row_len).enumerate() {
+            let start = ((region.y + i as u32) * self.size + region.x) as usize * bpp;
+            self.data[start..start + row_len].copy_from_slice(row);
+        }
+        self.dirty = self.dirty.union(region);
+    }
+}
+
+/// Texture pages holding rasterized glyphs. Pages are added as they fill up, and the renderer
+/// picks up new texels with `take_updates`.
+///
+#[derive(Clone, Debug)]
+pub struct TextureAtlas {
+    page_size: u32,
+    padding: u32,
+    max_pages: Option<usize>,
+    pages: Vec<AtlasPage>,
+}
+
+impl TextureAtlas {
+    pub fn new(page_size: u32) -> Self {
+        Self {
+            page_size,
+            padding: 1,
+            max_pages: None,
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Limit the number of pages, of all formats together. Inserts that need a new page past
+    /// the limit take an empty page of another format, or fail, and the atlas has to be
+    /// cleared to make room.
+    ///
+    pub fn set_max_pages(&mut self, max_pages: Option<usize>) {
+        self.max_pages = max_pages;
+    }
+
+    pub fn max_pages(&self) -> Option<usize> {
+        self.max_pages
+    }
+
+    /// No more pages can be added
+    ///
+    pub fn is_full(&self) -> bool {
+        self.max_pages.is_some_and(|max| self.pages.len() >= max)
+    }
+
+    pub fn pages(&self) -> &[AtlasPage] {
+        &self.pages
+    }
+
+    pub fn page(&self, index: usize) -> Option<&AtlasPage> {
+        self.pages.get(index)
+    }
+
+    /// Whether a region fits in an empty page: it is not empty, no larger than a page, and
+    /// `texels` is its size
+    ///
+    pub fn accepts(&self, format: AtlasFormat, width: u32, height: u32, texels: &[u8]) -> bool {
+        width > 0
+            && height > 0
+            && width + self.padding <= self.page_size
+            && height + self.padding <= self.page_size
+            && texels.len() == width as usize * height as usize * format.bytes_per_pixel()
+    }
+
+    /// Allocate a region in a page of `format` and fill it with `texels`, which are tightly
+    /// packed rows. Returns the page index and the region, or `None` if the region is empty,
+    /// larger than a page, or `texels` is not the size of the region, or if it needs a new page
+    /// and the atlas is full with no empty page to take.
+    ///
+    pub fn insert(
+        &mut self,
+        format: AtlasFormat,
+        width: u32,
+        height: u32,
+        texels: &[u8],
+    ) -> Option<(usize, AtlasRegion)> {
+        if !self.accepts(format, width, height, texels) {
+            return None;
+        }
+        let padding = self.padding;
+        let found = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, page)| page.format == format)
+            .find_map(|(index, page)| {
+                page.allocate(width, height, padding)
+                    .map(|region| (index, region))
+            });
+
+        let (index, region) = match found {
+            Some(found) => found,
+            None if self.is_full() => {
+                // an empty page of another format is made again in this one
+                let index = self
+                    .pages
+                    .iter()
+                    .position(|page| page.format != format && page.shelves.is_empty())?;
+                let mut page = AtlasPage::new(format, self.page_size);
+                let region = page.allocate(width, height, padding)?;
+                self.pages[index] = page;
+                (index, region)
+            }
+            None => {
+                let mut page = AtlasPage::new(format, self.page_size);
+                let region = page.allocate(width, height, padding)?;
+                self.pages.push(page);
+                (self.pages.len() - 1, region)
+            }
+        };
+        self.pages[index].write(&region, texels);
+        Some((index, region))
+    }
+
+    /// The regions written since the last call, at most one per page
+    ///
+    pub fn take_updates(&mut self) -> Vec<AtlasUpdate> {
+        let mut updates = Vec::new();
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            if page.dirty.is_empty() && !page.new_page {
+                continue;
+            }
+            updates.push(AtlasUpdate {
+                page: index,
+                new_page: page.new_page,
+                region: page.dirty,
+            });
+            page.dirty = AtlasRegion::default();
+            page.new_page = false;
+        }
+        updates
+    }
+
+    /// Empty every page. The pages keep their textures, so each is reported as fully dirty on
+    /// the next `take_updates`.
+    ///
+    pub fn clear(&mut self) {
+        for page in &mut self.pages {
+            page.data.fill(0);
+            page.shelves.clear();
+            page.dirty = AtlasRegion {
+                x: 0,
+                y: 0,
+                width: page.size,
+                height: page.size,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atlas::{AtlasFormat, AtlasRegion, TextureAtlas};
+
+    #[test]
+    fn shelf_packing() {
+        let mut atlas = TextureAtlas::new(16);
+        let (page, a) = atlas.insert(AtlasFormat::Alpha, 4, 4, &[255; 16]).unwrap();
+        let (_, b) = atlas.insert(AtlasFormat::Alpha, 4, 3, &[128; 12]).unwrap();
+        let (_, c) = atlas.insert(AtlasFormat::Alpha, 4, 8, &[64; 32]).unwrap();
+        assert_eq!(page, 0);
+        // b shares a's shelf, c is too tall and starts a new one
+        assert_eq!(
+            a,
+            AtlasRegion {
+                x: 0,
+                y: 0,
+                width: 4,
+                height: 4
+            }
+        );
+        assert_eq!(
+            b,
+            AtlasRegion {
+                x: 5,
+                y: 0,
+                width: 4,
+                height: 3
+            }
+        );
+        assert_eq!(
+            c,
+            AtlasRegion {
+                x: 0,
+                y: 5,
+                width: 4,
+                height: 8
+            }
+        );
+
+        let page = atlas.page(0).unwrap();
+        assert_eq!(page.region_data(&b), vec![128; 12]);
+        assert_eq!(page.data()[4], 0);
+        assert_eq!(page.uv_rect(&a).size.width, 0.25);
+
+        // too big for any page, empty, or with the wrong number of texels
+        assert!(atlas.insert(AtlasFormat::Alpha, 17, 1, &[0; 17]).is_none());
+        assert!(atlas.insert(AtlasFormat::Alpha, 0, 0, &[]).is_none());
+        assert!(atlas.insert(AtlasFormat::Alpha, 0, 4, &[]).is_none());
+        assert!(atlas.insert(AtlasFormat::Alpha, 2, 2, &[0; 3]).is_none());
+        assert!(atlas.insert(AtlasFormat::Rgba, 2, 2, &[0; 4]).is_none());
+        assert!(!atlas.accepts(AtlasFormat::Alpha, 17, 1, &[0; 17]));
+        assert!(atlas.accepts(AtlasFormat::Rgba, 2, 2, &[0; 16]));
+    }
+
+    #[test]
+    fn pages_and_updates() {
+        let mut atlas = TextureAtlas::new(8);
+        atlas.insert(AtlasFormat::Alpha, 7, 7, &[1; 49]).unwrap();
+        let (page, _) = atlas.insert(AtlasFormat::Alpha, 2, 2, &[1; 4]).unwrap();
+        assert_eq!(page, 1);
+        let (page, _) = atlas.insert(AtlasFormat::Rgba, 2, 2, &[1; 16]).unwrap();
+        assert_eq!(page, 2);
+
+        let updates = atlas.take_updates();
+        assert_eq!(updates.len(), 3);
+        assert!(updates.iter().all(|u| u.new_page));
+        assert_eq!(
+            updates[0].region,
+            AtlasRegion {
+                x: 0,
+                y: 0,
+                width: 7,
+                height: 7
+            }
+        );
+        assert!(atlas.take_updates().is_empty());
+
+        // the dirty region grows to cover every write, across shelves
+        atlas.insert(AtlasFormat::Alpha, 2, 2, &[1; 4]).unwrap();
+        atlas.insert(AtlasFormat::Alpha, 2, 2, &[1; 4]).unwrap();
+        let updates = atlas.take_updates();
+        assert_eq!(updates.len(), 1);
+        assert!(!updates[0].new_page);
+        assert_eq!(
+            updates[0].region,
+            AtlasRegion {
+                x: 0,
+                y: 0,
+                width: 5,
+                height: 5
+            }
+        );
+
+        // a full atlas only fills the space left in its pages
+        atlas.set_max_pages(Some(3));
+        assert!(atlas.is_full());
+        assert!(atlas.insert(AtlasFormat::Alpha, 2, 2, &[1; 4]).is_some());
+        assert!(atlas.insert(AtlasFormat::Alpha, 7, 7, &[1; 49]).is_none());
+
+        atlas.clear();
+        let updates = atlas.take_updates();
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[1].region.width, 8);
+        assert!(atlas.page(1).unwrap().data().iter().all(|&t| t == 0));
+
+        // once cleared, an empty page can change format
+        assert_eq!(
+            atlas.insert(AtlasFormat::Rgba, 7, 7, &[1; 196]).unwrap().0,
+            2
+        );
+        assert_eq!(
+            atlas.insert(AtlasFormat::Rgba, 7, 7, &[1; 196]).unwrap().0,
+            0
+        );
+        assert_eq!(atlas.page(0).unwrap().format(), AtlasFormat::Rgba);
+        let updates = atlas.take_updates();
+        assert!(updates[0].new_page && !updates[1].new_page);
+        atlas.insert(AtlasFormat::Alpha, 7, 7, &[1; 49]).unwrap();
+        assert!(atlas.insert(AtlasFormat::Alpha, 7, 7, &[1; 49]).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::tessellate::Geometry;
+use crate::{Point, Rect, Size, Transform2D};
+
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct CluiVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+    /// Texture coordinates in the draw's atlas page. Unused by untextured draws.
+    pub uv: [f32; 2],
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct CluiDrawList {
+    pub draw_sets: Vec<CluiDrawSet>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CluiDrawSet {
+    pub viewport: Size,
+    pub scissor: Rect,
+    pub draws: Vec<DrawInstruction>,
+    pub vertices: Vec<CluiVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// A single indexed draw. Indices are relative to `vertex_offset`.
+///
+/// Textured draws sample an atlas page. For `AtlasFormat::Alpha` pages the vertex color is
+/// multiplied by the coverage in the red channel, for `AtlasFormat::Rgba` pages by the texel.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct DrawInstruction {
+    pub index_offset: u32,
+    pub index_count: u32,
+    pub vertex_offset: u32,
+    /// Scissor rect in viewport coordinates
+    pub scissor: Rect,
+    /// Index of the atlas page to sample, or `None` for solid color geometry
+    pub texture: Option<usize>,
+}
+
+impl CluiDrawSet {
+    pub(crate) fn new(viewport: Size, scissor: Rect) -> Self {
+        Self {
+            viewport,
+            scissor,
+            draws: Vec::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Append the geometry of one window, mapped through `transform`. Consecutive geometry
+    /// with the same scissor rect and texture is merged into one draw instruction, otherwise a
+    /// new instruction is started.
+    ///
+    pub(crate) fn append(
+        &mut self,
+        geometry: &Geometry,
+        scissor: Rect,
+        transform: &Transform2D,
+        texture: Option<usize>,
+    ) {
+        if geometry.is_empty() {
+            return;
+        }
+        let scissor = transform.transform_rect(&scissor);
+
+        let draw = match self.draws.last_mut() {
+            Some(draw) if draw.scissor == scissor && draw.texture == texture => draw,
+            _ => {
+                self.draws.push(DrawInstruction {
+                    index_offset: self.indices.len() as u32,
+                    index_count: 0,
+                    vertex_offset: self.vertices.len() as u32,
+                    scissor,
+                    texture,
+                });
+                self.draws.last_mut().unwrap()
+            }
+        };
+
+        let base = self.vertices.len() as u32 - draw.vertex_offset;
+        draw.index_count += geometry.indices.len() as u32;
+        self.vertices.extend(geometry.vertices.iter().map(|v| {
+            let p = transform.transform_point(Point::from_xy(v.position[0], v.position[1]));
+            CluiVertex {
+                position: [p.x, p.y],
+                ..*v
+            }
+        }));
+        self.indices
+            .extend(geometry.indices.iter().map(|i| base + i));
+    }
+}
@@ -0,0 +1,39 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CluiError {
+    /// The window key is stale or belongs to another layer
+    InvalidWindowKey,
+    /// The layer key does not refer to a layer
+    InvalidLayerKey,
+    /// Reparenting would make a window its own ancestor
+    CyclicHierarchy,
+    /// The file could not be read, or no file read handler is set
+    FileRead(String),
+    /// The file is not a font that can be parsed
+    InvalidFont(String),
+    /// The file is not an image that can be decoded
+    InvalidImage(String),
+    /// Text markup could not be parsed
+    InvalidMarkup(String),
+    /// Layout could not be resolved. clui-layout logs windows whose size limits conflict
+    /// rather than failing with this.
+    Layout(String),
+}
+
+impl fmt::Display for CluiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CluiError::InvalidWindowKey => write!(f, "invalid window key"),
+            CluiError::InvalidLayerKey => write!(f, "invalid layer key"),
+            CluiError::CyclicHierarchy => write!(f, "window can not be its own ancestor"),
+            CluiError::FileRead(path) => write!(f, "failed to read file '{}'", path),
+            CluiError::InvalidFont(path) => write!(f, "'{}' is not a valid font", path),
+            CluiError::InvalidImage(path) => write!(f, "'{}' is not a valid image", path),
+            CluiError::InvalidMarkup(msg) => write!(f, "invalid markup: {}", msg),
+            CluiError::Layout(msg) => write!(f, "layout failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CluiError {}
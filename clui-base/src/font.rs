@@ -0,0 +1,814 @@
+use crate::atlas::{AtlasFormat, AtlasRegion, AtlasUpdate, TextureAtlas};
+use crate::{CluiError, Point, Rect, Scalar};
+use ab_glyph::{Font as _, FontVec, GlyphId, GlyphImageFormat, PxScale, ScaleFont};
+use slotmap::{new_key_type, SlotMap};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+new_key_type! { pub struct FontKey; }
+new_key_type! { pub struct FontCollectionKey; }
+
+/// The styles a font collection has fonts for
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub enum FontStyle {
+    #[default]
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontStyle {
+    pub fn from_flags(bold: bool, italic: bool) -> Self {
+        match (bold, italic) {
+            (false, false) => FontStyle::Regular,
+            (true, false) => FontStyle::Bold,
+            (false, true) => FontStyle::Italic,
+            (true, true) => FontStyle::BoldItalic,
+        }
+    }
+
+    pub fn is_bold(&self) -> bool {
+        matches!(self, FontStyle::Bold | FontStyle::BoldItalic)
+    }
+
+    pub fn is_italic(&self) -> bool {
+        matches!(self, FontStyle::Italic | FontStyle::BoldItalic)
+    }
+}
+
+/// A named family of fonts. Each style has an ordered list of fonts: characters missing from
+/// the first are taken from the next one that has them.
+///
+#[derive(Clone, Default, Debug)]
+pub struct FontCollection {
+    name: String,
+    styles: HashMap<FontStyle, Vec<FontKey>>,
+}
+
+impl FontCollection {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            styles: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set the fonts of one style, the primary font first
+    ///
+    pub fn set_fonts(&mut self, style: FontStyle, fonts: Vec<FontKey>) {
+        self.styles.insert(style, fonts);
+    }
+
+    /// The fonts for a style. A style with no fonts uses the nearest one that has them, bold
+    /// italic trying bold then italic, and everything ending at regular.
+    ///
+    pub fn fonts(&self, style: FontStyle) -> &[FontKey] {
+        let nearest: &[FontStyle] = match style {
+            FontStyle::Regular => &[FontStyle::Regular],
+            FontStyle::Bold => &[FontStyle::Bold, FontStyle::Regular],
+            FontStyle::Italic => &[FontStyle::Italic, FontStyle::Regular],
+            FontStyle::BoldItalic => &[
+                FontStyle::BoldItalic,
+                FontStyle::Bold,
+                FontStyle::Italic,
+                FontStyle::Regular,
+            ],
+        };
+        nearest
+            .iter()
+            .filter_map(|style| self.styles.get(style))
+            .find(|fonts| !fonts.is_empty())
+            .map_or(&[], |fonts| fonts)
+    }
+}
+
+/// Vertical metrics of a font at one size, in pixels. Both ascent and descent are distances
+/// from the baseline, so they are positive for most fonts.
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct FontMetrics {
+    pub ascent: Scalar,
+    pub descent: Scalar,
+    pub line_gap: Scalar,
+}
+
+impl FontMetrics {
+    /// The distance between baselines the font asks for
+    ///
+    pub fn line_height(&self) -> Scalar {
+        self.ascent + self.descent + self.line_gap
+    }
+}
+
+/// A parsed TrueType or OpenType font. Sizes are em sizes in pixels, as in CSS `font-size`.
+///
+pub struct Font {
+    name: String,
+    font: FontVec,
+}
+
+impl Font {
+    pub fn from_data(name: &str, data: Vec<u8>) -> Result<Self, CluiError> {
+        let font =
+            FontVec::try_from_vec(data).map_err(|_| CluiError::InvalidFont(name.to_string()))?;
+        Ok(Self {
+            name: name.to_string(),
+            font,
+        })
+    }
+
+    /// The path or name the font was loaded with
+    ///
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn px_scale(&self, size: Scalar) -> PxScale {
+        let units_per_em = self.font.units_per_em().unwrap_or(1000.0);
+        PxScale::from(size * self.font.height_unscaled() / units_per_em)
+    }
+
+    pub fn metrics(&self, size: Scalar) -> FontMetrics {
+        let scaled = self.font.as_scaled(self.px_scale(size));
+        FontMetrics {
+            ascent: scaled.ascent(),
+            descent: -scaled.descent(),
+            line_gap: scaled.line_gap(),
+        }
+    }
+
+    /// The glyph for a character, or `None` if the font does not cover it
+    ///
+    pub fn glyph_id(&self, c: char) -> Option<u16> {
+        match self.font.glyph_id(c) {
+            GlyphId(0) => None,
+            GlyphId(id) => Some(id),
+        }
+    }
+
+    /// How far the pen moves after drawing `glyph`
+    ///
+    pub fn advance(&self, glyph: u16, size: Scalar) -> Scalar {
+        self.font
+            .as_scaled(self.px_scale(size))
+            .h_advance(GlyphId(glyph))
+    }
+
+    /// Extra space between a pair of glyphs from the font's kerning table
+    ///
+    pub fn kern(&self, first: u16, second: u16, size: Scalar) -> Scalar {
+        self.font
+            .as_scaled(self.px_scale(size))
+            .kern(GlyphId(first), GlyphId(second))
+    }
+
+    pub(crate) fn font_data(&self) -> &[u8] {
+        self.font.font_data()
+    }
+
+    /// Rasterize one glyph. Glyphs with a color bitmap, as in CBDT and sbix emoji fonts, are
+    /// scaled from the nearest strike to RGBA. Others are rasterized from their outline to
+    /// coverage values.
+    ///
+    fn rasterize(&self, glyph: u16, size: Scalar) -> Option<RasterGlyph> {
+        self.color_bitmap(glyph, size)
+            .or_else(|| self.rasterize_outline(glyph, size))
+    }
+
+    fn color_bitmap(&self, glyph: u16, size: Scalar) -> Option<RasterGlyph> {
+        let strike_size = size.round().clamp(1.0, u16::MAX as Scalar) as u16;
+        let image = self.font.glyph_raster_image2(GlyphId(glyph), strike_size)?;
+        let (width, height, texels) = match image.format {
+            GlyphImageFormat::Png => decode_png(image.data)?,
+            GlyphImageFormat::BitmapPremulBgra32 => {
+                let (width, height) = (image.width as u32, image.height as u32);
+                let texels = unpremultiply_bgra(image.data, width, height)?;
+                (width, height, texels)
+            }
+            _ => return None,
+        };
+
+        // offsets are in strike pixels, from the bottom left, y up
+        let scale = size / image.pixels_per_em.max(1) as Scalar;
+        let target_width = (width as Scalar * scale).round().max(1.0) as u32;
+        let target_height = (height as Scalar * scale).round().max(1.0) as u32;
+        Some(RasterGlyph {
+            format: AtlasFormat::Rgba,
+            width: target_width,
+            height: target_height,
+            offset: Point::from_xy(
+                image.origin.x * scale,
+                -(image.origin.y + image.height as Scalar) * scale,
+            ),
+            texels: resample_rgba(&texels, width, height, target_width, target_height),
+        })
+    }
+
+    fn rasterize_outline(&self, glyph: u16, size: Scalar) -> Option<RasterGlyph> {
+        let glyph = GlyphId(glyph).with_scale(self.px_scale(size));
+        let outlined = self.font.outline_glyph(glyph)?;
+        let bounds = outlined.px_bounds();
+        let width = bounds.width() as u32;
+        let height = bounds.height() as u32;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut coverage = vec![0; (width * height) as usize];
+        outlined.draw(|x, y, c| {
+            if x < width && y < height {
+                coverage[(y * width + x) as usize] = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        });
+        Some(RasterGlyph {
+            format: AtlasFormat::Alpha,
+            width,
+            height,
+            offset: Point::from_xy(bounds.min.x, bounds.min.y),
+            texels: coverage,
+        })
+    }
+}
+
+/// A rasterized glyph, with the offset of its top left corner from the pen position on the
+/// baseline
+///
+struct RasterGlyph {
+    format: AtlasFormat,
+    width: u32,
+    height: u32,
+    offset: Point,
+    texels: Vec<u8>,
+}
+
+/// Decode a PNG glyph image to straight RGBA
+///
+pub(crate) fn decode_png(data: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()?];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    let pixels = (info.width * info.height) as usize;
+    let texels = match info.color_type {
+        png::ColorType::Rgba => buffer[..pixels * 4].to_vec(),
+        png::ColorType::Rgb => buffer[..pixels * 3]
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer[..pixels * 2]
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer[..pixels]
+            .iter()
+            .flat_map(|p| [*p, *p, *p, 255])
+            .collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some((info.width, info.height, texels))
+}
+
+/// Convert premultiplied BGRA to straight RGBA
+///
+fn unpremultiply_bgra(data: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    let data = data.get(..(width * height) as usize * 4)?;
+    Some(
+        data.chunks_exact(4)
+            .flat_map(|p| {
+                let unpremultiply =
+                    |c: u8| (c as u32 * 255 + p[3] as u32 / 2).checked_div(p[3] as u32);
+                let [r, g, b] = [p[2], p[1], p[0]].map(|c| unpremultiply(c).unwrap_or(0) as u8);
+                [r, g, b, p[3]]
+            })
+            .collect(),
+    )
+}
+
+/// Scale an RGBA image with bilinear filtering
+///
+fn resample_rgba(texels: &[u8], width: u32, height: u32, to_width: u32, to_height: u32) -> Vec<u8> {
+    if (width, height) == (to_width, to_height) {
+        return texels.to_vec();
+    }
+    let texel = |x: u32, y: u32, c: usize| texels[(y * width + x) as usize * 4 + c] as Scalar;
+    let mut out = Vec::with_capacity((to_width * to_height) as usize * 4);
+    for y in 0..to_height {
+        let sy = ((y as Scalar + 0.5) * height as Scalar / to_height as Scalar - 0.5)
+            .clamp(0.0, (height - 1) as Scalar);
+        let (y0, fy) = (sy.floor() as u32, sy.fract());
+        let y1 = (y0 + 1).min(height - 1);
+        for x in 0..to_width {
+            let sx = ((x as Scalar + 0.5) * width as Scalar / to_width as Scalar - 0.5)
+                .clamp(0.0, (width - 1) as Scalar);
+            let (x0, fx) = (sx.floor() as u32, sx.fract());
+            let x1 = (x0 + 1).min(width - 1);
+            for c in 0..4 {
+                let top = texel(x0, y0, c) * (1.0 - fx) + texel(x1, y0, c) * fx;
+                let bottom = texel(x0, y1, c) * (1.0 - fx) + texel(x1, y1, c) * fx;
+                out.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+            }
+        }
+    }
+    out
+}
+
+/// A glyph rasterized into the atlas
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct AtlasGlyph {
+    pub page: usize,
+    /// `AtlasFormat::Rgba` for color glyphs, which are drawn with their own colors
+    pub format: AtlasFormat,
+    pub region: AtlasRegion,
+    /// Normalized texture coordinates of the region
+    pub uv: Rect,
+    /// Position of the bitmap's top left corner relative to the pen position on the baseline,
+    /// in pixels
+    pub offset: Point,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct GlyphCacheKey {
+    font: FontKey,
+    glyph: u16,
+    // in quarter pixels
+    size: u32,
+}
+
+/// An image drawn inline with text. The texels are kept so the image can be added again when
+/// the atlas is cleared.
+///
+struct InlineImageData {
+    width: u32,
+    height: u32,
+    texels: Vec<u8>,
+    glyph: Option<AtlasGlyph>,
+}
+
+/// The loaded fonts and the atlas their glyphs are rasterized into. Glyphs are rasterized the
+/// first time they are asked for at a given size, then cached. Images drawn inline with text
+/// share the atlas.
+///
+pub struct Fonts {
+    fonts: SlotMap<FontKey, Font>,
+    collections: SlotMap<FontCollectionKey, FontCollection>,
+    images: HashMap<String, InlineImageData>,
+    glyphs: HashMap<GlyphCacheKey, Option<AtlasGlyph>>,
+    atlas: TextureAtlas,
+    generation: u64,
+}
+
+// Unique across every `Fonts`, so text laid out with one set is never mistaken as current
+// for another
+fn next_generation() -> u64 {
+    static GENERATION: AtomicU64 = AtomicU64::new(1);
+    GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Fonts {
+    pub fn new() -> Self {
+        Self {
+            fonts: SlotMap::with_key(),
+            collections: SlotMap::with_key(),
+            images: HashMap::new(),
+            glyphs: HashMap::new(),
+            atlas: Self::new_atlas(1024, Some(Self::MAX_ATLAS_PAGES)),
+            generation: next_generation(),
+        }
+    }
+
+    // Enough for the glyphs of a few sizes in several scripts. Past this the atlas is emptied
+    // rather than grown, so glyphs left over from old sizes and scales are dropped.
+    const MAX_ATLAS_PAGES: usize = 4;
+
+    fn new_atlas(page_size: u32, max_pages: Option<usize>) -> TextureAtlas {
+        let mut atlas = TextureAtlas::new(page_size);
+        atlas.set_max_pages(max_pages);
+        atlas
+    }
+
+    /// Changes whenever fonts are added or removed, or cached glyphs are dropped. Text laid out
+    /// against an older generation has to be laid out again.
+    ///
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn add_font(&mut self, name: &str, data: Vec<u8>) -> Result<FontKey, CluiError> {
+        let key = self.fonts.insert(Font::from_data(name, data)?);
+        self.generation = next_generation();
+        Ok(key)
+    }
+
+    /// Remove a font. Its glyphs stay in the atlas until it is cleared.
+    ///
+    pub fn remove_font(&mut self, key: FontKey) -> Option<Font> {
+        self.glyphs.retain(|k, _| k.font != key);
+        self.generation = next_generation();
+        self.fonts.remove(key)
+    }
+
+    pub fn font(&self, key: FontKey) -> Option<&Font> {
+        self.fonts.get(key)
+    }
+
+    /// Find a loaded font by the name it was loaded with
+    ///
+    pub fn find_font(&self, name: &str) -> Option<FontKey> {
+        self.fonts
+            .iter()
+            .find(|(_, font)| font.name == name)
+            .map(|(key, _)| key)
+    }
+
+    /// Add a font collection. Text styles that name it are laid out with its fonts.
+    ///
+    pub fn add_collection(&mut self, collection: FontCollection) -> FontCollectionKey {
+        self.generation = next_generation();
+        self.collections.insert(collection)
+    }
+
+    pub fn remove_collection(&mut self, key: FontCollectionKey) -> Option<FontCollection> {
+        self.generation = next_generation();
+        self.collections.remove(key)
+    }
+
+    pub fn collection(&self, key: FontCollectionKey) -> Option<&FontCollection> {
+        self.collections.get(key)
+    }
+
+    /// Change a collection. Text using it is laid out again.
+    ///
+    pub fn collection_mut(&mut self, key: FontCollectionKey) -> Option<&mut FontCollection> {
+        self.generation = next_generation();
+        self.collections.get_mut(key)
+    }
+
+    pub fn find_collection(&self, name: &str) -> Option<FontCollectionKey> {
+        self.collections
+            .iter()
+            .find(|(_, collection)| collection.name == name)
+            .map(|(key, _)| key)
+    }
+
+    /// Get a glyph from the atlas, rasterizing it at `pixel_size` if this is the first use.
+    /// Sizes are rounded to a quarter pixel. Color glyphs go into RGBA pages, separate from
+    /// the coverage of outline glyphs. Returns `None` for glyphs with nothing to draw, such as
+    /// spaces, and for glyphs too large for an atlas page.
+    ///
+    /// When the atlas has no room left every glyph is dropped, as with `clear_glyphs`, and the
+    /// generation changes. Glyphs asked for earlier in the frame have to be asked for again.
+    ///
+    pub fn glyph(&mut self, font: FontKey, glyph: u16, pixel_size: Scalar) -> Option<AtlasGlyph> {
+        let key = GlyphCacheKey {
+            font,
+            glyph,
+            size: (pixel_size * 4.0).round().max(0.0) as u32,
+        };
+        if let Some(cached) = self.glyphs.get(&key) {
+            return *cached;
+        }
+
+        let raster = match self.fonts.get(font) {
+            Some(f) => f.rasterize(glyph, key.size as Scalar / 4.0),
+            None => return None,
+        };
+        let glyph = raster.and_then(|raster| {
+            let insert = |atlas: &mut TextureAtlas| {
+                atlas.insert(raster.format, raster.width, raster.height, &raster.texels)
+            };
+            let (page, region) = match insert(&mut self.atlas) {
+                None if self.atlas.is_full()
+                    && self.atlas.accepts(
+                        raster.format,
+                        raster.width,
+                        raster.height,
+                        &raster.texels,
+                    ) =>
+                {
+                    self.clear_glyphs();
+                    insert(&mut self.atlas)?
+                }
+                inserted => inserted?,
+            };
+            Some(AtlasGlyph {
+                page,
+                format: raster.format,
+                region,
+                uv: self.atlas.page(page)?.uv_rect(&region),
+                offset: raster.offset,
+            })
+        });
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    /// Add a straight RGBA image to the atlas, to be drawn in text by name. An image with the
+    /// same name is replaced. Returns `None`, keeping any image with the same name, if the
+    /// image is larger than an atlas page or `texels` does not match its size. A full atlas is
+    /// cleared to make room, as in `glyph`.
+    ///
+    pub fn add_image(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        texels: Vec<u8>,
+    ) -> Option<AtlasGlyph> {
+        let mut image = InlineImageData {
+            width,
+            height,
+            texels,
+            glyph: None,
+        };
+        image.glyph = match insert_image(&mut self.atlas, &image) {
+            None if self.atlas.is_full()
+                && self
+                    .atlas
+                    .accepts(AtlasFormat::Rgba, width, height, &image.texels) =>
+            {
+                self.clear_glyphs();
+                insert_image(&mut self.atlas, &image)
+            }
+            inserted => inserted,
+        };
+        let glyph = image.glyph?;
+        self.generation = next_generation();
+        self.images.insert(name.to_string(), image);
+        Some(glyph)
+    }
+
+    pub fn remove_image(&mut self, name: &str) -> bool {
+        self.generation = next_generation();
+        self.images.remove(name).is_some()
+    }
+
+    /// Where an image is in the atlas. Its size in texels is the size of the region.
+    ///
+    pub fn image(&self, name: &str) -> Option<AtlasGlyph> {
+        self.images.get(name)?.glyph
+    }
+
+    // Put every image back after the atlas is emptied
+    fn insert_images(&mut self) {
+        for image in self.images.values_mut() {
+            image.glyph = insert_image(&mut self.atlas, image);
+        }
+    }
+
+    pub fn atlas(&self) -> &TextureAtlas {
+        &self.atlas
+    }
+
+    /// The atlas regions the renderer has to upload before drawing the next frame
+    ///
+    pub fn take_atlas_updates(&mut self) -> Vec<AtlasUpdate> {
+        self.atlas.take_updates()
+    }
+
+    /// Drop every rasterized glyph, for example after the scale factor changes. Glyphs are
+    /// rasterized again as they are used, and images are added back right away.
+    ///
+    pub fn clear_glyphs(&mut self) {
+        self.glyphs.clear();
+        self.atlas.clear();
+        self.insert_images();
+        self.generation = next_generation();
+    }
+
+    /// Limit the number of atlas pages, 4 to begin with. When the glyphs in use need more the
+    /// atlas is emptied, and they are rasterized again as they are drawn.
+    ///
+    pub fn set_max_atlas_pages(&mut self, max_pages: Option<usize>) {
+        self.atlas.set_max_pages(max_pages);
+    }
+
+    pub fn max_atlas_pages(&self) -> Option<usize> {
+        self.atlas.max_pages()
+    }
+
+    /// Change the size of new atlas pages. This drops every glyph and page, so the renderer
+    /// receives new pages on the next update. Images are added to the new pages.
+    ///
+    pub fn set_atlas_page_size(&mut self, page_size: u32) {
+        self.glyphs.clear();
+        self.atlas = Self::new_atlas(page_size, self.atlas.max_pages());
+        self.insert_images();
+        self.generation = next_generation();
+    }
+}
+
+fn insert_image(atlas: &mut TextureAtlas, image: &InlineImageData) -> Option<AtlasGlyph> {
+    let (page, region) =
+        atlas.insert(AtlasFormat::Rgba, image.width, image.height, &image.texels)?;
+    Some(AtlasGlyph {
+        page,
+        format: AtlasFormat::Rgba,
+        region,
+        uv: atlas.page(page)?.uv_rect(&region),
+        offset: Point::default(),
+    })
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_font_data() -> Vec<u8> {
+    std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/fonts/DejaVuSans.ttf"
+    ))
+    .unwrap()
+}
+
+/// A lighter font that covers fewer scripts, for testing fallback
+///
+#[cfg(test)]
+pub(crate) fn test_light_font_data() -> Vec<u8> {
+    std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/fonts/DejaVuSans-ExtraLight.ttf"
+    ))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::font::{
+        decode_png, resample_rgba, test_font_data, unpremultiply_bgra, FontCollection, FontStyle,
+        Fonts,
+    };
+    use crate::{AtlasFormat, CluiError};
+
+    #[test]
+    fn font_metrics() {
+        let mut fonts = Fonts::new();
+        let key = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        assert_eq!(fonts.find_font("DejaVuSans.ttf"), Some(key));
+
+        let font = fonts.font(key).unwrap();
+        let metrics = font.metrics(20.0);
+        // 1901 and 483 units to the 2048 unit em
+        assert!((metrics.ascent - 18.56).abs() < 0.01);
+        assert!((metrics.descent - 4.72).abs() < 0.01);
+
+        let a = font.glyph_id('A').unwrap();
+        assert!(font.advance(a, 20.0) > 10.0);
+        assert_eq!(font.advance(a, 40.0), font.advance(a, 20.0) * 2.0);
+        assert_eq!(font.glyph_id('\u{e000}'), None);
+
+        assert_eq!(
+            fonts.add_font("bad.ttf", vec![0; 16]).err(),
+            Some(CluiError::InvalidFont("bad.ttf".to_string()))
+        );
+    }
+
+    #[test]
+    fn glyphs_are_cached_in_the_atlas() {
+        let mut fonts = Fonts::new();
+        let key = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        let font = fonts.font(key).unwrap();
+        let a = font.glyph_id('A').unwrap();
+        let space = font.glyph_id(' ').unwrap();
+
+        let glyph = fonts.glyph(key, a, 32.0).unwrap();
+        assert!(glyph.region.width > 10 && glyph.region.height > 10);
+        // the top of the A is above the baseline
+        assert!(glyph.offset.y < -15.0);
+        let page = fonts.atlas().page(glyph.page).unwrap();
+        assert!(page.region_data(&glyph.region).contains(&255));
+
+        assert_eq!(fonts.glyph(key, a, 32.0), Some(glyph));
+        assert_eq!(fonts.glyph(key, a, 32.1), Some(glyph));
+        assert_eq!(fonts.glyph(key, space, 32.0), None);
+
+        let updates = fonts.take_atlas_updates();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].region, glyph.region);
+
+        // a new size is a new glyph
+        let bigger = fonts.glyph(key, a, 64.0).unwrap();
+        assert_ne!(bigger.region, glyph.region);
+        assert_eq!(fonts.take_atlas_updates()[0].region, bigger.region);
+
+        let generation = fonts.generation();
+        fonts.clear_glyphs();
+        assert_ne!(fonts.generation(), generation);
+        assert_eq!(fonts.take_atlas_updates()[0].region.width, 1024);
+        assert_eq!(fonts.glyph(key, a, 32.0).unwrap().region.x, 0);
+
+        fonts.remove_font(key);
+        assert_eq!(fonts.glyph(key, a, 32.0), None);
+    }
+
+    #[test]
+    fn full_atlas_is_cleared() {
+        let mut fonts = Fonts::new();
+        let key = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        let a = fonts.font(key).unwrap().glyph_id('A').unwrap();
+        fonts.set_atlas_page_size(64);
+
+        // each size takes a new page, until the limit
+        for size in 1..=4 {
+            fonts.glyph(key, a, 60.0 + size as f32).unwrap();
+        }
+        assert_eq!(fonts.atlas().pages().len(), 4);
+        let generation = fonts.generation();
+        let glyph = fonts.glyph(key, a, 70.0).unwrap();
+        assert_ne!(fonts.generation(), generation);
+        assert_eq!(fonts.atlas().pages().len(), 4);
+        assert_eq!((glyph.page, glyph.region.x, glyph.region.y), (0, 0, 0));
+        // the old sizes are rasterized again when used
+        assert_eq!(fonts.glyph(key, a, 61.0).unwrap().page, 1);
+
+        // images make room the same way, and one that does not fit keeps the old image
+        fonts.set_atlas_page_size(64);
+        for size in 1..=4 {
+            fonts.glyph(key, a, 60.0 + size as f32).unwrap();
+        }
+        let fire = fonts
+            .add_image("fire", 40, 40, vec![255; 40 * 40 * 4])
+            .unwrap();
+        assert_eq!((fire.page, fire.region.x, fire.region.y), (0, 0, 0));
+        assert_eq!(fonts.image("fire"), Some(fire));
+        assert_eq!(
+            fonts.add_image("fire", 80, 80, vec![255; 80 * 80 * 4]),
+            None
+        );
+        assert_eq!(fonts.add_image("fire", 8, 8, vec![255; 3]), None);
+        assert_eq!(fonts.image("fire"), Some(fire));
+    }
+
+    #[test]
+    fn collection_styles() {
+        let mut fonts = Fonts::new();
+        let regular = fonts.add_font("regular.ttf", test_font_data()).unwrap();
+        let fallback = fonts.add_font("fallback.ttf", test_font_data()).unwrap();
+        let bold = fonts.add_font("bold.ttf", test_font_data()).unwrap();
+
+        let mut collection = FontCollection::new("sans");
+        assert!(collection.fonts(FontStyle::Regular).is_empty());
+        collection.set_fonts(FontStyle::Regular, vec![regular, fallback]);
+        collection.set_fonts(FontStyle::Bold, vec![bold, fallback]);
+        let generation = fonts.generation();
+        let key = fonts.add_collection(collection);
+        assert_ne!(fonts.generation(), generation);
+        assert_eq!(fonts.find_collection("sans"), Some(key));
+
+        let collection = fonts.collection(key).unwrap();
+        assert_eq!(collection.fonts(FontStyle::Regular), [regular, fallback]);
+        assert_eq!(collection.fonts(FontStyle::Italic), [regular, fallback]);
+        assert_eq!(collection.fonts(FontStyle::BoldItalic), [bold, fallback]);
+        assert_eq!(FontStyle::from_flags(true, true), FontStyle::BoldItalic);
+        assert!(FontStyle::BoldItalic.is_italic() && !FontStyle::Italic.is_bold());
+    }
+
+    #[test]
+    fn color_bitmaps() {
+        // a 2x1 png, red then half transparent green
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[255, 0, 0, 255, 0, 255, 0, 128])
+            .unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            decode_png(&png_data),
+            Some((2, 1, vec![255, 0, 0, 255, 0, 255, 0, 128]))
+        );
+        assert_eq!(decode_png(&[1, 2, 3]), None);
+
+        // premultiplied blue at half alpha
+        assert_eq!(
+            unpremultiply_bgra(&[64, 0, 0, 128, 0, 0, 0, 0], 2, 1),
+            Some(vec![0, 0, 128, 128, 0, 0, 0, 0])
+        );
+        assert_eq!(unpremultiply_bgra(&[0; 4], 2, 1), None);
+
+        let scaled = resample_rgba(&[0, 0, 0, 255, 200, 200, 200, 255], 2, 1, 4, 2);
+        assert_eq!(scaled.len(), 32);
+        assert_eq!(&scaled[..4], &[0, 0, 0, 255]);
+        assert_eq!(&scaled[12..16], &[200, 200, 200, 255]);
+        assert_eq!(scaled[4], 50);
+
+        // outline fonts still rasterize to coverage
+        let mut fonts = Fonts::new();
+        let key = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        let a = fonts.font(key).unwrap().glyph_id('A').unwrap();
+        let glyph = fonts.glyph(key, a, 16.0).unwrap();
+        assert_eq!(glyph.format, AtlasFormat::Alpha);
+    }
+}
@@ -1,36 +1,519 @@
 #![allow(unused)]
 
-use crate::{CluiColor, Point, Rect, Scalar, Size};
+use crate::tessellate::{self, Geometry, TessellationOptions};
+use crate::text::{self, CluiText, TextLayout};
+use crate::{
+    BoxModel, BoxRects, CluiColor, CluiDrawSet, CluiError, CluiPositioning, Fonts, LengthContext,
+    Offsets, Padding, Point, Rect, Scalar, Size, Transform2D,
+};
 use slotmap::{new_key_type, HopSlotMap};
+use std::ops::{Deref, DerefMut};
 
 pub struct CluiLayer {
-    windows: HopSlotMap<CluiWindowKey, CluiUiRect>,
+    windows: HopSlotMap<CluiWindowKey, WindowNode>,
+    // top level windows, in the order they were added
+    roots: Vec<CluiWindowKey>,
+    // in logical pixels
     viewport: Size,
+    canvas_scaling: CanvasScaling,
+    // physical pixels per logical pixel
+    scale_factor: Scalar,
+    root_font_size: Scalar,
+    tessellation_options: TessellationOptions,
+    // windows changed since the last update, may contain removed keys
+    dirty_windows: Vec<CluiWindowKey>,
+    paint_order: Vec<CluiWindowKey>,
+    paint_order_dirty: bool,
+    // `Fonts::generation` the text of every window was last laid out with
+    text_generation: u64,
+    // windows rebuilt by `update` without fonts, whose text still has to be laid out again
+    stale_text: Vec<CluiWindowKey>,
+    // windows whose geometry `update` rebuilt, for tests to check nothing more was redone
+    #[cfg(test)]
+    pub(crate) geometry_rebuilds: usize,
+}
+
+struct WindowNode {
+    window: CluiUiRect,
+    parent: Option<CluiWindowKey>,
+    children: Vec<CluiWindowKey>,
+    // screen space rects from the last update
+    box_rects: BoxRects,
+    // what the box model was resolved in, with the window's own font size
+    context: LengthContext,
+    border_widths: Padding,
+    // screen space area this window is clipped to by its ancestors, if any
+    clip: Option<Rect>,
+    geometry: Geometry,
+    text_layout: Option<TextLayout>,
+    // glyph quads, one geometry per atlas page
+    text_geometry: Vec<(usize, Geometry)>,
+    dirty: bool,
+    // z_index at the time paint_order was last built
+    sorted_z_index: i32,
 }
 
 new_key_type! { pub struct CluiWindowKey; }
 
+// Where a parent places its children, what it clips them to, and what their lengths are
+// resolved against
+#[derive(Copy, Clone)]
+struct Placement {
+    origin: Point,
+    clip: Option<Rect>,
+    context: LengthContext,
+}
+
+/// Mutable access to a window. Any mutable use marks the window dirty so the next
+/// `CluiLayer::update` will rebuild it.
+///
+pub struct CluiWindowMut<'a> {
+    layer: &'a mut CluiLayer,
+    key: CluiWindowKey,
+}
+
+impl<'a> Deref for CluiWindowMut<'a> {
+    type Target = CluiUiRect;
+
+    fn deref(&self) -> &CluiUiRect {
+        &self.layer.windows[self.key].window
+    }
+}
+
+impl<'a> DerefMut for CluiWindowMut<'a> {
+    fn deref_mut(&mut self) -> &mut CluiUiRect {
+        self.layer.mark_dirty(self.key);
+        &mut self.layer.windows[self.key].window
+    }
+}
+
 impl CluiLayer {
+    /// Set the size of the area the layer is drawn to, in logical pixels
+    ///
     pub fn update_viewport(&mut self, width: Scalar, height: Scalar) {
-        self.viewport = Size { width, height }
+        let old_size = self.viewport();
+        let old_scale = self.canvas_scale();
+        self.viewport = Size { width, height };
+        // everything may need placing again, and curves tessellating at the new scale
+        if self.viewport() != old_size || self.canvas_scale() != old_scale {
+            self.mark_all_dirty();
+        }
+    }
+
+    /// The size of the layer in layout units. This is the pixel viewport divided by the canvas
+    /// scale.
+    ///
+    pub fn viewport(&self) -> Size {
+        self.viewport / self.canvas_scale()
+    }
+
+    /// The size of the layer in logical pixels, as given to `update_viewport`
+    ///
+    pub fn pixel_viewport(&self) -> Size {
+        self.viewport
+    }
+
+    /// The size of the layer in physical pixels, which is the size of the draw list viewport
+    ///
+    pub fn physical_viewport(&self) -> Size {
+        self.viewport * self.scale_factor
+    }
+
+    pub fn set_canvas_scaling(&mut self, canvas_scaling: CanvasScaling) {
+        if canvas_scaling != self.canvas_scaling {
+            self.canvas_scaling = canvas_scaling;
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn canvas_scaling(&self) -> CanvasScaling {
+        self.canvas_scaling
+    }
+
+    /// Logical pixels per layout unit, from the canvas scaling mode
+    ///
+    pub fn canvas_scale(&self) -> Scalar {
+        self.canvas_scaling.scale(self.viewport)
+    }
+
+    /// Physical pixels per layout unit. Geometry is tessellated and snapped at this scale, and
+    /// glyphs should be rasterized at it.
+    ///
+    pub fn physical_scale(&self) -> Scalar {
+        self.canvas_scale() * self.scale_factor
+    }
+
+    /// Set the number of physical pixels per logical pixel, as reported by the windowing system.
+    /// Layout is unaffected, and the next update rebuilds all geometry for the new density.
+    ///
+    pub fn set_scale_factor(&mut self, scale_factor: Scalar) {
+        let scale_factor = valid_scale_factor(scale_factor);
+        if scale_factor != self.scale_factor {
+            self.scale_factor = scale_factor;
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn scale_factor(&self) -> Scalar {
+        self.scale_factor
+    }
+
+    /// Set the font size of `Rem` lengths, and of `Em` lengths in windows without a font size
+    /// of their own or of an ancestor. 16 by default.
+    ///
+    pub fn set_root_font_size(&mut self, size: Scalar) {
+        if size != self.root_font_size {
+            self.root_font_size = size;
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn root_font_size(&self) -> Scalar {
+        self.root_font_size
+    }
+
+    /// What the lengths of top level windows are resolved against. Their percentages are of
+    /// the viewport.
+    ///
+    pub fn root_length_context(&self) -> LengthContext {
+        LengthContext {
+            font_size: self.root_font_size,
+            root_font_size: self.root_font_size,
+            viewport: self.viewport(),
+            parent_content: self.viewport(),
+        }
+    }
+
+    pub fn set_tessellation_options(&mut self, options: TessellationOptions) {
+        if options != self.tessellation_options {
+            self.tessellation_options = options;
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn tessellation_options(&self) -> &TessellationOptions {
+        &self.tessellation_options
     }
 
+    /// Bring screen rects, geometry and paint order up to date. Only windows changed since the
+    /// last update, and descendants that moved as a result, are recalculated. A window's rect is
+    /// relative to its parent's screen position.
+    ///
+    /// No fonts are available here, so text is not laid out again. Windows with text that
+    /// move keep their glyphs, moved along, and are laid out on the next `update_with_fonts`
+    /// or `Clui::update`.
+    ///
     pub fn update(&mut self) {
-        todo!()
+        self.update_windows(None);
+    }
+
+    /// Update the layer as `update` does, laying out the text of windows with `fonts` and
+    /// rasterizing their glyphs into its atlas. Text is laid out again whenever the window is
+    /// rebuilt or the fonts change.
+    ///
+    pub fn update_with_fonts(&mut self, fonts: &mut Fonts) {
+        if fonts.generation() != self.text_generation {
+            self.text_generation = fonts.generation();
+            let with_text: Vec<CluiWindowKey> = self
+                .windows
+                .iter()
+                .filter(|(_, node)| node.window.text.is_some() || node.text_layout.is_some())
+                .map(|(key, _)| key)
+                .collect();
+            for key in with_text {
+                self.mark_dirty(key);
+            }
+        }
+        for key in std::mem::take(&mut self.stale_text) {
+            if self.windows.contains_key(key) {
+                self.mark_dirty(key);
+            }
+        }
+        self.update_windows(Some(fonts));
+    }
+
+    fn update_windows(&mut self, mut fonts: Option<&mut Fonts>) {
+        let mut dirty = std::mem::take(&mut self.dirty_windows);
+        dirty.retain(|k| self.windows.contains_key(*k));
+        // parents first, so a dirty descendant is normally handled while walking its ancestor
+        dirty.sort_by_cached_key(|k| self.depth(*k));
+
+        for key in dirty {
+            if !self.windows[key].dirty {
+                continue;
+            }
+            let placement = match self.windows[key].parent {
+                Some(parent) => self.child_placement(parent),
+                None => Placement {
+                    origin: Point::default(),
+                    clip: None,
+                    context: self.root_length_context(),
+                },
+            };
+            self.update_window(key, placement, true, false, fonts.as_deref_mut());
+        }
+
+        if self.paint_order_dirty {
+            let mut order = std::mem::take(&mut self.paint_order);
+            order.clear();
+            let roots = self.roots.clone();
+            self.push_stacking_context(&roots, &mut order);
+            self.paint_order = order;
+            self.paint_order_dirty = false;
+        }
+    }
+
+    // `container_changed` is set when a positioned ancestor was rebuilt, so absolute windows
+    // below it are revisited even if their parents did not move
+    fn update_window(
+        &mut self,
+        key: CluiWindowKey,
+        parent: Placement,
+        force: bool,
+        container_changed: bool,
+        mut fonts: Option<&mut Fonts>,
+    ) {
+        let tessellation_scale = self.physical_scale();
+        let canvas_scale = self.canvas_scale();
+        let (screen_rect, clip) = self.placement(key, parent.origin, parent.clip);
+        let node = &mut self.windows[key];
+        let model = &node.window.box_model;
+        let context = model.length_context(&parent.context);
+        let box_rects = model.resolve(&screen_rect, &context);
+        let border_widths = model.border.widths().resolve(&context);
+        // relative lengths follow the parent, so a window can change without moving
+        let changed = box_rects != node.box_rects || border_widths != node.border_widths;
+        let clip_changed = clip != node.clip;
+        let rebuild = force || changed || node.dirty;
+        // a new context may still change the lengths of descendants
+        if !rebuild && !clip_changed && !container_changed && context == node.context {
+            return;
+        }
+        node.clip = clip;
+        node.context = context;
+
+        // a new clip alone changes what is culled, not the geometry
+        if rebuild {
+            let old_content = node.box_rects.content.point;
+            node.box_rects = box_rects;
+            node.border_widths = border_widths;
+            node.dirty = false;
+            if node.window.z_index != node.sorted_z_index {
+                self.paint_order_dirty = true;
+            }
+
+            node.geometry.clear();
+            tessellate::add_box(
+                &mut node.geometry,
+                model,
+                &node.border_widths,
+                &node.box_rects,
+                &self.tessellation_options,
+                tessellation_scale,
+                canvas_scale,
+            );
+
+            match fonts.as_deref_mut() {
+                Some(fonts) => {
+                    node.text_geometry.clear();
+                    node.text_layout = node.window.text.as_ref().map(|text| {
+                        let content = node.box_rects.content;
+                        let layout = text.layout(fonts, Some(content.size.width));
+                        text::tessellate_text(
+                            &mut node.text_geometry,
+                            &layout,
+                            content.point,
+                            fonts,
+                            tessellation_scale,
+                        );
+                        layout
+                    });
+                }
+                None if node.window.text.is_some() || node.text_layout.is_some() => {
+                    let delta = node.box_rects.content.point - old_content;
+                    for (_, geometry) in &mut node.text_geometry {
+                        geometry.translate(delta);
+                    }
+                    self.stale_text.push(key);
+                }
+                None => {}
+            }
+            #[cfg(test)]
+            {
+                self.geometry_rebuilds += 1;
+            }
+        }
+
+        let container_changed = container_changed
+            || (rebuild && self.windows[key].window.positioning != CluiPositioning::Static);
+        let placement = self.child_placement(key);
+        for i in 0..self.windows[key].children.len() {
+            let child = self.windows[key].children[i];
+            self.update_window(
+                child,
+                placement,
+                false,
+                container_changed,
+                fonts.as_deref_mut(),
+            );
+        }
+    }
+
+    // The screen rect and clip of a window, given where its parent places children
+    fn placement(
+        &self,
+        key: CluiWindowKey,
+        origin: Point,
+        clip: Option<Rect>,
+    ) -> (Rect, Option<Rect>) {
+        let window = &self.windows[key].window;
+        let static_rect = window.rect.translate(origin);
+        let offsets = &window.offsets;
+
+        match window.positioning {
+            CluiPositioning::Static => (static_rect, clip),
+            CluiPositioning::Relative => {
+                let dx = offsets.left.or(offsets.right.map(|r| -r)).unwrap_or(0.0);
+                let dy = offsets.top.or(offsets.bottom.map(|b| -b)).unwrap_or(0.0);
+                (static_rect.translate(Point::from_xy(dx, dy)), clip)
+            }
+            CluiPositioning::Absolute => {
+                let (container, clip) = self.containing_block(key);
+                let (x, width) = Self::position_axis(
+                    container.left(),
+                    container.width(),
+                    offsets.left,
+                    offsets.right,
+                    static_rect.left(),
+                    static_rect.width(),
+                );
+                let (y, height) = Self::position_axis(
+                    container.top(),
+                    container.height(),
+                    offsets.top,
+                    offsets.bottom,
+                    static_rect.top(),
+                    static_rect.height(),
+                );
+                (Rect::from_values(x, y, width, height), clip)
+            }
+        }
+    }
+
+    // The padding box of the nearest positioned ancestor, shifted by its scroll offset, and the
+    // clip it applies to its children. The viewport, unclipped, if there is no such ancestor.
+    fn containing_block(&self, key: CluiWindowKey) -> (Rect, Option<Rect>) {
+        let mut current = self.windows[key].parent;
+        while let Some(k) = current {
+            let node = &self.windows[k];
+            if node.window.positioning != CluiPositioning::Static {
+                let padding = node.box_rects.padding.translate(-node.window.scroll_offset);
+                return (padding, self.child_placement(k).clip);
+            }
+            current = node.parent;
+        }
+        (
+            Rect::from_pos_and_size(Point::default(), self.viewport()),
+            None,
+        )
+    }
+
+    // Start and size of an absolute window along one axis. With both offsets set the window
+    // stretches between them, with one it keeps its size, with neither it stays where the
+    // flow put it.
+    fn position_axis(
+        start: Scalar,
+        length: Scalar,
+        offset_start: Option<Scalar>,
+        offset_end: Option<Scalar>,
+        static_start: Scalar,
+        size: Scalar,
+    ) -> (Scalar, Scalar) {
+        match (offset_start, offset_end) {
+            (Some(a), Some(b)) => (start + a, Scalar::max(length - a - b, 0.0)),
+            (Some(a), None) => (start + a, size),
+            (None, Some(b)) => (start + length - b - size, size),
+            (None, None) => (static_start, size),
+        }
+    }
+
+    // Where the children of a window are placed from, what they are clipped to and what their
+    // lengths are resolved against. Children sit in the parent's content box, shifted by its
+    // scroll offset.
+    fn child_placement(&self, parent: CluiWindowKey) -> Placement {
+        let node = &self.windows[parent];
+        let content = node.box_rects.content;
+        let origin = content.point - node.window.scroll_offset;
+
+        let clip = match node.window.overflow {
+            Overflow::Visible => node.clip,
+            Overflow::Hidden | Overflow::Scroll => Some(match node.clip {
+                // an empty rect at the content origin if the clips do not meet
+                Some(clip) => clip
+                    .intersect(&content)
+                    .unwrap_or_else(|| Rect::from_pos_and_size(content.point, Size::default())),
+                None => content,
+            }),
+        };
+        Placement {
+            origin,
+            clip,
+            context: LengthContext {
+                parent_content: content.size,
+                ..node.context
+            },
+        }
+    }
+
+    fn depth(&self, key: CluiWindowKey) -> usize {
+        let mut depth = 0;
+        let mut current = self.windows[key].parent;
+        while let Some(k) = current {
+            depth += 1;
+            current = self.windows[k].parent;
+        }
+        depth
+    }
+
+    fn mark_all_dirty(&mut self) {
+        let keys: Vec<CluiWindowKey> = self.windows.keys().collect();
+        for key in keys {
+            self.mark_dirty(key);
+        }
+    }
+
+    fn mark_dirty(&mut self, key: CluiWindowKey) {
+        let node = &mut self.windows[key];
+        if !node.dirty {
+            node.dirty = true;
+            self.dirty_windows.push(key);
+        }
     }
 
     pub(crate) fn new() -> CluiLayer {
         CluiLayer {
             windows: HopSlotMap::with_key(),
+            roots: Vec::new(),
             viewport: Size {
                 width: 800.0,
                 height: 600.0,
             },
+            canvas_scaling: CanvasScaling::default(),
+            scale_factor: 1.0,
+            root_font_size: 16.0,
+            tessellation_options: TessellationOptions::default(),
+            dirty_windows: Vec::new(),
+            paint_order: Vec::new(),
+            paint_order_dirty: false,
+            text_generation: 0,
+            stale_text: Vec::new(),
+            #[cfg(test)]
+            geometry_rebuilds: 0,
         }
     }
 
     pub fn add_default_window(&mut self) -> CluiWindowKey {
-        self.windows.insert(CluiUiRect {
+        self.add_window(CluiUiRect {
             rect: Rect {
                 point: Point { x: 0.0, y: 0.0 },
                 size: Size {
@@ -38,44 +521,1213 @@ impl CluiLayer {
                     height: 0.0,
                 },
             },
-            //            screen_rect: CluiRect::default(),
-            background_color: CluiColor::default(),
-            z_index: 0,
-            //          parent: 0,
+            ..Default::default()
         })
     }
 
+    /// Add a top level window
+    ///
     pub fn add_window(&mut self, window: CluiUiRect) -> CluiWindowKey {
-        self.windows.insert(window)
+        let key = self.insert_node(window, None);
+        self.roots.push(key);
+        key
     }
 
+    /// Add a window as the last child of `parent`
+    ///
+    pub fn add_child_window(
+        &mut self,
+        parent: CluiWindowKey,
+        window: CluiUiRect,
+    ) -> Result<CluiWindowKey, CluiError> {
+        if !self.windows.contains_key(parent) {
+            return Err(CluiError::InvalidWindowKey);
+        }
+        let key = self.insert_node(window, Some(parent));
+        self.windows[parent].children.push(key);
+        Ok(key)
+    }
+
+    fn insert_node(&mut self, window: CluiUiRect, parent: Option<CluiWindowKey>) -> CluiWindowKey {
+        let key = self.windows.insert(WindowNode {
+            sorted_z_index: window.z_index,
+            window,
+            parent,
+            children: Vec::new(),
+            box_rects: BoxRects::default(),
+            context: LengthContext::default(),
+            border_widths: Padding::default(),
+            clip: None,
+            geometry: Geometry::default(),
+            text_layout: None,
+            text_geometry: Vec::new(),
+            dirty: false,
+        });
+        self.mark_dirty(key);
+        self.paint_order_dirty = true;
+        key
+    }
+
+    /// Move a window, along with its children, to the end of `new_parent`'s children, or to the
+    /// top level if `new_parent` is `None`. Fails if either key is invalid or if `new_parent` is
+    /// the window itself or one of its descendants.
+    ///
+    pub fn reparent_window(
+        &mut self,
+        key: CluiWindowKey,
+        new_parent: Option<CluiWindowKey>,
+    ) -> Result<(), CluiError> {
+        if !self.windows.contains_key(key) {
+            return Err(CluiError::InvalidWindowKey);
+        }
+        if let Some(parent) = new_parent {
+            if !self.windows.contains_key(parent) {
+                return Err(CluiError::InvalidWindowKey);
+            }
+            if self.is_ancestor_or_self(key, parent) {
+                return Err(CluiError::CyclicHierarchy);
+            }
+        }
+
+        self.detach(key);
+        self.windows[key].parent = new_parent;
+        match new_parent {
+            Some(parent) => self.windows[parent].children.push(key),
+            None => self.roots.push(key),
+        }
+        self.mark_dirty(key);
+        self.paint_order_dirty = true;
+        Ok(())
+    }
+
+    fn is_ancestor_or_self(&self, ancestor: CluiWindowKey, key: CluiWindowKey) -> bool {
+        let mut current = Some(key);
+        while let Some(k) = current {
+            if k == ancestor {
+                return true;
+            }
+            current = self.windows[k].parent;
+        }
+        false
+    }
+
+    // Unlink a window from its parent's child list (or the root list)
+    fn detach(&mut self, key: CluiWindowKey) {
+        let siblings = match self.windows[key].parent {
+            Some(parent) => &mut self.windows[parent].children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|k| *k != key);
+    }
+
+    /// Remove a window and all of its descendants
+    ///
     pub fn remove_window(&mut self, key: CluiWindowKey) -> Option<CluiUiRect> {
-        self.windows.remove(key)
+        if !self.windows.contains_key(key) {
+            return None;
+        }
+        self.detach(key);
+        self.paint_order_dirty = true;
+        self.remove_subtree(key)
+    }
+
+    fn remove_subtree(&mut self, key: CluiWindowKey) -> Option<CluiUiRect> {
+        let node = self.windows.remove(key)?;
+        for child in node.children {
+            self.remove_subtree(child);
+        }
+        Some(node.window)
+    }
+
+    pub fn get_mut_window_by_key(&mut self, key: CluiWindowKey) -> Option<CluiWindowMut<'_>> {
+        if self.windows.contains_key(key) {
+            Some(CluiWindowMut { layer: self, key })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_window_by_key(&self, key: CluiWindowKey) -> Option<&CluiUiRect> {
+        self.windows.get(key).map(|node| &node.window)
+    }
+
+    /// Panics if the key is not valid
+    ///
+    pub fn get_mut_window_by_key_unchecked(&mut self, key: CluiWindowKey) -> CluiWindowMut<'_> {
+        self.get_mut_window_by_key(key).unwrap()
+    }
+
+    /// Panics if the key is not valid
+    ///
+    pub fn get_window_by_key_unchecked(&self, key: CluiWindowKey) -> &CluiUiRect {
+        self.get_window_by_key(key).unwrap()
+    }
+
+    pub fn contains_window(&self, key: CluiWindowKey) -> bool {
+        self.windows.contains_key(key)
+    }
+
+    pub fn parent(&self, key: CluiWindowKey) -> Option<CluiWindowKey> {
+        self.windows.get(key).and_then(|node| node.parent)
+    }
+
+    /// The children of a window in the order they were added
+    ///
+    pub fn children(&self, key: CluiWindowKey) -> &[CluiWindowKey] {
+        self.windows
+            .get(key)
+            .map(|node| node.children.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn root_windows(&self) -> &[CluiWindowKey] {
+        &self.roots
+    }
+
+    /// The screen rect calculated by the last call to `update`
+    ///
+    pub fn screen_rect(&self, key: CluiWindowKey) -> Option<Rect> {
+        self.windows.get(key).map(|node| node.box_rects.margin)
+    }
+
+    /// The screen space box rects calculated by the last call to `update`
+    ///
+    pub fn box_rects(&self, key: CluiWindowKey) -> Option<BoxRects> {
+        self.windows.get(key).map(|node| node.box_rects)
+    }
+
+    /// The layout of a window's text from the last update, relative to its content box
+    ///
+    pub fn text_layout(&self, key: CluiWindowKey) -> Option<&TextLayout> {
+        self.windows.get(key)?.text_layout.as_ref()
     }
 
-    pub fn get_mut_window_by_key(&mut self, key: CluiWindowKey) -> &mut CluiUiRect {
-        self.windows.get_mut(key).unwrap()
+    /// The order windows are drawn in, back to front, as of the last update. Every window is a
+    /// stacking context: its children are drawn on top of it, sorted amongst themselves by
+    /// z_index with ties drawn in the order they were added, and never interleave with windows
+    /// outside the parent.
+    ///
+    pub fn paint_order(&self) -> &[CluiWindowKey] {
+        &self.paint_order
     }
 
-    pub fn get_window_by_key(&self, key: CluiWindowKey) -> &CluiUiRect {
-        self.windows.get(key).unwrap()
+    fn push_stacking_context(&mut self, members: &[CluiWindowKey], order: &mut Vec<CluiWindowKey>) {
+        let mut sorted = members.to_vec();
+        // a stable sort keeps insertion order for equal z_index
+        sorted.sort_by_key(|k| self.windows[*k].window.z_index);
+
+        for key in sorted {
+            let node = &mut self.windows[key];
+            node.sorted_z_index = node.window.z_index;
+            let children = node.children.clone();
+            order.push(key);
+            self.push_stacking_context(&children, order);
+        }
+    }
+
+    /// Windows entirely outside their clip rect, or the viewport, are culled here. Everything
+    /// else is drawn with its clip rect as the scissor. Geometry and scissors are scaled from
+    /// layout units to physical pixels.
+    ///
+    pub(crate) fn build_draw_set(&self) -> CluiDrawSet {
+        let viewport = Rect::from_pos_and_size(Point::default(), self.viewport());
+        let to_pixels = Transform2D::scale(self.physical_scale(), self.physical_scale());
+        let mut draw_set = CluiDrawSet::new(
+            self.physical_viewport(),
+            Rect::from_pos_and_size(Point::default(), self.physical_viewport()),
+        );
+        let feather = self.tessellation_options.feather(self.canvas_scale());
+
+        for key in &self.paint_order {
+            let node = &self.windows[*key];
+            let scissor = match node.clip {
+                Some(clip) => match clip.intersect(&viewport) {
+                    Some(scissor) => scissor,
+                    None => continue,
+                },
+                None => viewport,
+            };
+
+            // zero sized bounds are kept unless they are outside the scissor entirely
+            let visible = |bounds: Rect| {
+                if bounds.is_empty() {
+                    bounds.intersect(&scissor).is_some()
+                } else {
+                    bounds.overlaps(&scissor)
+                }
+            };
+
+            let border = node.box_rects.border;
+            if visible(border.outset(&Padding::all(feather))) {
+                draw_set.append(&node.geometry, scissor, &to_pixels, None);
+            }
+            // text can run past its box, so it is culled by where its glyphs are
+            for (page, geometry) in &node.text_geometry {
+                if geometry.bounds().is_some_and(visible) {
+                    draw_set.append(geometry, scissor, &to_pixels, Some(*page));
+                }
+            }
+        }
+
+        draw_set
     }
 }
 
-#[derive(Clone, Default, Copy)]
+/// How layout units map to logical pixels. Layout, hit testing and the window rects all work in
+/// units, and the draw list is scaled to physical pixels.
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CanvasScaling {
+    /// A unit is `scale` pixels whatever the viewport size
+    ConstantPixelSize { scale: Scalar },
+    /// The `reference` size in units is scaled to fit the viewport. `match_height` blends, in
+    /// log space, between matching the widths at 0 and matching the heights at 1.
+    ScaleWithScreenSize {
+        reference: Size,
+        match_height: Scalar,
+    },
+    /// A unit is a fixed physical size, `units_per_inch` to the inch. Screens are taken to be
+    /// 96 logical pixels to the inch, with the scale factor accounting for denser displays.
+    ConstantPhysicalSize { units_per_inch: Scalar },
+}
+
+impl Default for CanvasScaling {
+    fn default() -> Self {
+        CanvasScaling::ConstantPixelSize { scale: 1.0 }
+    }
+}
+
+impl CanvasScaling {
+    /// Logical pixels per unit for a viewport in logical pixels
+    ///
+    pub fn scale(&self, viewport: Size) -> Scalar {
+        let scale = match *self {
+            CanvasScaling::ConstantPixelSize { scale } => scale,
+            CanvasScaling::ScaleWithScreenSize {
+                reference,
+                match_height,
+            } => {
+                if reference.is_empty() || viewport.is_empty() {
+                    return 1.0;
+                }
+                let log_width = (viewport.width / reference.width).ln();
+                let log_height = (viewport.height / reference.height).ln();
+                let t = match_height.clamp(0.0, 1.0);
+                (log_width + (log_height - log_width) * t).exp()
+            }
+            CanvasScaling::ConstantPhysicalSize { units_per_inch } => 96.0 / units_per_inch,
+        };
+        // a zero or broken scale would make the layer infinitely large
+        if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        }
+    }
+}
+
+/// How children that extend past a window's content box are handled
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum Overflow {
+    /// Children are drawn in full
+    #[default]
+    Visible,
+    /// Children are clipped to the content box
+    Hidden,
+    /// Children are clipped to the content box and shifted by the scroll offset
+    Scroll,
+}
+
+/// A window is placed at `rect`, relative to its parent's content box, and its box model is
+/// resolved inside that rect. Positioned windows are then moved by their offsets.
+///
+#[derive(Clone, Default)]
 pub struct CluiUiRect {
     pub(crate) rect: Rect,
-    //screen_rect: CluiRect,
-    pub(crate) background_color: CluiColor,
+    pub(crate) box_model: BoxModel,
     pub(crate) z_index: i32,
-    //parent: usize,
+    pub(crate) overflow: Overflow,
+    pub(crate) scroll_offset: Point,
+    pub(crate) positioning: CluiPositioning,
+    pub(crate) offsets: Offsets,
+    pub(crate) text: Option<CluiText>,
+}
+
+impl CluiUiRect {
+    pub fn new(rect: Rect, background_color: CluiColor) -> Self {
+        Self {
+            rect,
+            box_model: BoxModel {
+                background_color,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn with_box_model(rect: Rect, box_model: BoxModel) -> Self {
+        Self {
+            rect,
+            box_model,
+            ..Default::default()
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn set_rect(&mut self, rect: Rect) {
+        self.rect = rect;
+    }
+
+    pub fn box_model(&self) -> &BoxModel {
+        &self.box_model
+    }
+
+    pub fn box_model_mut(&mut self) -> &mut BoxModel {
+        &mut self.box_model
+    }
+
+    pub fn background_color(&self) -> CluiColor {
+        self.box_model.background_color
+    }
+
+    pub fn set_background_color(&mut self, color: CluiColor) {
+        self.box_model.background_color = color;
+    }
+
+    pub fn z_index(&self) -> i32 {
+        self.z_index
+    }
+
+    pub fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    pub fn scroll_offset(&self) -> Point {
+        self.scroll_offset
+    }
+
+    /// How far the content has been scrolled. Only used with `Overflow::Scroll`.
+    ///
+    pub fn set_scroll_offset(&mut self, offset: Point) {
+        self.scroll_offset = offset;
+    }
+
+    pub fn positioning(&self) -> CluiPositioning {
+        self.positioning
+    }
+
+    pub fn set_positioning(&mut self, positioning: CluiPositioning) {
+        self.positioning = positioning;
+    }
+
+    /// True for absolute windows, which layout containers should skip
+    ///
+    pub fn is_out_of_flow(&self) -> bool {
+        self.positioning == CluiPositioning::Absolute
+    }
+
+    pub fn offsets(&self) -> &Offsets {
+        &self.offsets
+    }
+
+    pub fn set_offsets(&mut self, offsets: Offsets) {
+        self.offsets = offsets;
+    }
+
+    pub fn text(&self) -> Option<&CluiText> {
+        self.text.as_ref()
+    }
+
+    pub fn text_mut(&mut self) -> Option<&mut CluiText> {
+        self.text.as_mut()
+    }
+
+    /// Text drawn in the content box, wrapped to its width
+    ///
+    pub fn set_text(&mut self, text: Option<CluiText>) {
+        self.text = text;
+    }
+}
+
+/// A scale factor that is not finite and positive is taken as 1.0
+///
+pub(crate) fn valid_scale_factor(scale_factor: Scalar) -> Scalar {
+    if scale_factor.is_finite() && scale_factor > 0.0 {
+        scale_factor
+    } else {
+        1.0
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::font::test_font_data;
+    use crate::layer::{CanvasScaling, CluiLayer, CluiUiRect, Overflow};
+    use crate::{
+        BoxModel, CluiColor, CluiDrawSet, CluiError, CluiPositioning, CluiText, Fonts, Length,
+        Margin, MarginDef, Offsets, Padding, Point, Rect, Size, TextStyle,
+    };
+
     #[test]
     fn it_works2() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    fn window_with_z(z_index: i32) -> CluiUiRect {
+        CluiUiRect {
+            z_index,
+            ..Default::default()
+        }
+    }
+
+    // an opaque window, so it draws a background
+    fn window_at(x: f32, y: f32, width: f32, height: f32) -> CluiUiRect {
+        CluiUiRect::new(
+            Rect::from_values(x, y, width, height),
+            CluiColor {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0,
+            },
+        )
+    }
+
+    #[test]
+    fn paint_order_by_z_index_then_insertion() {
+        let mut layer = CluiLayer::new();
+        let a = layer.add_window(window_with_z(1));
+        let b = layer.add_window(window_with_z(0));
+        let c = layer.add_window(window_with_z(1));
+        let d = layer.add_window(window_with_z(-2));
+
+        layer.update();
+        assert_eq!(layer.paint_order(), [d, b, a, c]);
+    }
+
+    #[test]
+    fn paint_order_stable_after_removal() {
+        let mut layer = CluiLayer::new();
+        let keys: Vec<_> = (0..6).map(|_| layer.add_window(window_with_z(0))).collect();
+        layer.remove_window(keys[1]);
+        layer.remove_window(keys[3]);
+
+        // freed slots get reused, but new windows still go on top of their z_index peers
+        let e = layer.add_window(window_with_z(0));
+        let f = layer.add_window(window_with_z(0));
+
+        layer.update();
+        assert_eq!(
+            layer.paint_order(),
+            [keys[0], keys[2], keys[4], keys[5], e, f]
+        );
+    }
+
+    #[test]
+    fn paint_order_nested_stacking_contexts() {
+        let mut layer = CluiLayer::new();
+        let a = layer.add_window(window_with_z(0));
+        let b = layer.add_window(window_with_z(1));
+        let a1 = layer.add_child_window(a, window_with_z(100)).unwrap();
+        let a2 = layer.add_child_window(a, window_with_z(-5)).unwrap();
+        let b1 = layer.add_child_window(b, window_with_z(0)).unwrap();
+
+        // a high z_index child can not escape its parent's stacking context
+        layer.update();
+        assert_eq!(layer.paint_order(), [a, a2, a1, b, b1]);
+    }
+
+    #[test]
+    fn child_screen_rects() {
+        let mut layer = CluiLayer::new();
+        let root = layer.add_window(window_at(10.0, 20.0, 200.0, 200.0));
+        let child = layer
+            .add_child_window(root, window_at(5.0, 5.0, 50.0, 50.0))
+            .unwrap();
+        let grandchild = layer
+            .add_child_window(child, window_at(1.0, 2.0, 10.0, 10.0))
+            .unwrap();
+        layer.update();
+
+        assert_eq!(
+            layer.screen_rect(child),
+            Some(Rect::from_values(15.0, 25.0, 50.0, 50.0))
+        );
+        assert_eq!(
+            layer.screen_rect(grandchild),
+            Some(Rect::from_values(16.0, 27.0, 10.0, 10.0))
+        );
+
+        assert_eq!(layer.reparent_window(grandchild, None), Ok(()));
+        layer.update();
+        assert_eq!(
+            layer.screen_rect(grandchild),
+            Some(Rect::from_values(1.0, 2.0, 10.0, 10.0))
+        );
+        assert_eq!(layer.root_windows(), &[root, grandchild]);
+        assert!(layer.children(child).is_empty());
+    }
+
+    #[test]
+    fn reparent_rejects_cycles() {
+        let mut layer = CluiLayer::new();
+        let a = layer.add_default_window();
+        let b = layer.add_child_window(a, CluiUiRect::default()).unwrap();
+        let c = layer.add_child_window(b, CluiUiRect::default()).unwrap();
+
+        assert_eq!(
+            layer.reparent_window(a, Some(c)),
+            Err(CluiError::CyclicHierarchy)
+        );
+        assert_eq!(
+            layer.reparent_window(a, Some(a)),
+            Err(CluiError::CyclicHierarchy)
+        );
+        assert_eq!(layer.reparent_window(c, Some(a)), Ok(()));
+        assert_eq!(layer.children(a), &[b, c]);
+        assert_eq!(layer.parent(c), Some(a));
+    }
+
+    #[test]
+    fn remove_is_recursive() {
+        let mut layer = CluiLayer::new();
+        let a = layer.add_default_window();
+        let b = layer.add_child_window(a, CluiUiRect::default()).unwrap();
+        let c = layer.add_child_window(b, CluiUiRect::default()).unwrap();
+        let d = layer.add_default_window();
+
+        layer.remove_window(b);
+        assert!(layer.children(a).is_empty());
+        assert_eq!(layer.screen_rect(c), None);
+
+        layer.remove_window(a);
+        assert_eq!(layer.root_windows(), &[d]);
+        layer.update();
+        assert_eq!(layer.paint_order(), [d]);
+    }
+
+    #[test]
+    fn update_only_rebuilds_changed_windows() {
+        let mut layer = CluiLayer::new();
+        let root = layer.add_window(window_at(0.0, 0.0, 100.0, 100.0));
+        let child = layer
+            .add_child_window(root, window_at(10.0, 10.0, 20.0, 20.0))
+            .unwrap();
+        let other = layer.add_window(window_at(200.0, 0.0, 50.0, 50.0));
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, 3);
+
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, 3);
+
+        // a colour change rebuilds only that window
+        layer
+            .get_mut_window_by_key_unchecked(other)
+            .set_background_color(CluiColor {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            });
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, 4);
+
+        // moving a parent rebuilds its descendants as well
+        layer
+            .get_mut_window_by_key_unchecked(root)
+            .set_rect(Rect::from_values(5.0, 5.0, 100.0, 100.0));
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, 6);
+        assert_eq!(
+            layer.screen_rect(child),
+            Some(Rect::from_values(15.0, 15.0, 20.0, 20.0))
+        );
+
+        // resizing a parent does not move its children
+        layer
+            .get_mut_window_by_key_unchecked(root)
+            .set_rect(Rect::from_values(5.0, 5.0, 150.0, 150.0));
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, 7);
+
+        // reading through the guard does not dirty anything
+        assert_eq!(layer.get_mut_window_by_key_unchecked(child).z_index(), 0);
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, 7);
+    }
+
+    #[test]
+    fn z_index_change_reorders() {
+        let mut layer = CluiLayer::new();
+        let a = layer.add_default_window();
+        let b = layer.add_default_window();
+        layer.update();
+        assert_eq!(layer.paint_order(), [a, b]);
+
+        layer.get_mut_window_by_key_unchecked(a).set_z_index(1);
+        layer.update();
+        assert_eq!(layer.paint_order(), [b, a]);
+    }
+
+    #[test]
+    fn stale_keys() {
+        let mut layer = CluiLayer::new();
+        let a = layer.add_default_window();
+        let b = layer.add_child_window(a, CluiUiRect::default()).unwrap();
+        layer.remove_window(a);
+
+        assert!(layer.get_window_by_key(a).is_none());
+        assert!(layer.get_mut_window_by_key(b).is_none());
+        assert!(!layer.contains_window(b));
+        assert_eq!(
+            layer.add_child_window(a, CluiUiRect::default()),
+            Err(CluiError::InvalidWindowKey)
+        );
+        assert_eq!(
+            layer.reparent_window(b, None),
+            Err(CluiError::InvalidWindowKey)
+        );
+    }
+
+    #[test]
+    fn children_placed_in_parent_content_box() {
+        let mut layer = CluiLayer::new();
+        let root = layer.add_window(CluiUiRect::with_box_model(
+            Rect::from_values(10.0, 10.0, 100.0, 100.0),
+            BoxModel {
+                margin: Margin::all(MarginDef::Value(Length::Px(5.0))),
+                padding: Padding::all(10.0),
+                ..Default::default()
+            },
+        ));
+        let child = layer
+            .add_child_window(root, window_at(0.0, 0.0, 20.0, 20.0))
+            .unwrap();
+        layer.update();
+
+        assert_eq!(
+            layer.box_rects(root).unwrap().border,
+            Rect::from_values(15.0, 15.0, 90.0, 90.0)
+        );
+        assert_eq!(
+            layer.screen_rect(child),
+            Some(Rect::from_values(25.0, 25.0, 20.0, 20.0))
+        );
+
+        // changing padding moves children without touching the parent's rect
+        layer
+            .get_mut_window_by_key_unchecked(root)
+            .box_model_mut()
+            .padding = Padding::all(0.0);
+        layer.update();
+        assert_eq!(
+            layer.screen_rect(child),
+            Some(Rect::from_values(15.0, 15.0, 20.0, 20.0))
+        );
+    }
+
+    #[test]
+    fn lengths_resolved_against_parent() {
+        let mut layer = CluiLayer::new();
+        layer.update_viewport(400.0, 300.0);
+        let root = layer.add_window(CluiUiRect::with_box_model(
+            Rect::from_values(0.0, 0.0, 200.0, 100.0),
+            BoxModel {
+                padding: Padding::all(Length::Vw(5.0)),
+                font_size: Some(Length::Rem(2.0)),
+                ..Default::default()
+            },
+        ));
+        let child = layer
+            .add_child_window(
+                root,
+                CluiUiRect::with_box_model(
+                    Rect::from_values(0.0, 0.0, 100.0, 50.0),
+                    BoxModel {
+                        padding: Padding {
+                            left: Length::Percent(10.0),
+                            top: Length::Em(0.5),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        layer.update();
+        // 10% of the 160 wide content of the root, and half of its 32 font size
+        assert_eq!(
+            layer.box_rects(child).unwrap().content,
+            Rect::from_values(36.0, 36.0, 84.0, 34.0)
+        );
+
+        // widening the parent rebuilds the child, though its own rect is the same
+        layer
+            .get_mut_window_by_key_unchecked(root)
+            .set_rect(Rect::from_values(0.0, 0.0, 300.0, 100.0));
+        layer.update();
+        assert_eq!(
+            layer.box_rects(child).unwrap().content,
+            Rect::from_values(46.0, 36.0, 74.0, 34.0)
+        );
+
+        layer.set_root_font_size(10.0);
+        layer.update();
+        assert_eq!(
+            layer.box_rects(child).unwrap().content,
+            Rect::from_values(46.0, 30.0, 74.0, 40.0)
+        );
+    }
+
+    #[test]
+    fn overflow_clips_and_culls() {
+        let mut layer = CluiLayer::new();
+        let panel = layer.add_window(CluiUiRect::with_box_model(
+            Rect::from_values(100.0, 100.0, 200.0, 200.0),
+            BoxModel {
+                padding: Padding::all(10.0),
+                background_color: CluiColor {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 1.0,
+                },
+                ..Default::default()
+            },
+        ));
+        layer
+            .get_mut_window_by_key_unchecked(panel)
+            .set_overflow(Overflow::Hidden);
+        let inside = layer
+            .add_child_window(panel, window_at(0.0, 0.0, 20.0, 20.0))
+            .unwrap();
+        let partial = layer
+            .add_child_window(panel, window_at(170.0, 0.0, 20.0, 20.0))
+            .unwrap();
+        let outside = layer
+            .add_child_window(panel, window_at(500.0, 0.0, 20.0, 20.0))
+            .unwrap();
+        let nested = layer
+            .add_child_window(outside, window_at(0.0, 0.0, 5.0, 5.0))
+            .unwrap();
+        layer.update();
+
+        let content = Rect::from_values(110.0, 110.0, 180.0, 180.0);
+        assert_eq!(layer.windows[partial].clip, Some(content));
+        assert_eq!(layer.windows[nested].clip, Some(content));
+
+        let set = layer.build_draw_set();
+        // panel, inside and partial; outside and its child are culled
+        assert_eq!(set.vertices.len(), 12);
+        assert_eq!(set.draws.len(), 2);
+        assert_eq!(
+            set.draws[0].scissor,
+            Rect::from_values(0.0, 0.0, 800.0, 600.0)
+        );
+        assert_eq!(set.draws[0].index_count, 6);
+        assert_eq!(set.draws[1].scissor, content);
+        assert_eq!(set.draws[1].index_count, 12);
+        assert_eq!(set.draws[1].vertex_offset, 4);
+        assert_eq!(set.draws[1].index_offset, 6);
+        assert!(set.indices[6..].iter().all(|i| *i < 8));
+
+        // scrolling moves children but not the clip
+        layer
+            .get_mut_window_by_key_unchecked(panel)
+            .set_overflow(Overflow::Scroll);
+        layer
+            .get_mut_window_by_key_unchecked(panel)
+            .set_scroll_offset(Point::from_xy(0.0, 15.0));
+        layer.update();
+        assert_eq!(
+            layer.screen_rect(inside),
+            Some(Rect::from_values(110.0, 95.0, 20.0, 20.0))
+        );
+        assert_eq!(layer.windows[inside].clip, Some(content));
+
+        // making the panel visible again removes the clip from every descendant
+        layer
+            .get_mut_window_by_key_unchecked(panel)
+            .set_overflow(Overflow::Visible);
+        layer.update();
+        assert_eq!(layer.windows[nested].clip, None);
+        assert_eq!(layer.build_draw_set().draws.len(), 1);
+    }
+
+    fn positioned(positioning: CluiPositioning, rect: Rect, offsets: Offsets) -> CluiUiRect {
+        CluiUiRect {
+            rect,
+            positioning,
+            offsets,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn relative_windows_are_offset() {
+        let mut layer = CluiLayer::new();
+        let root = layer.add_window(window_at(10.0, 10.0, 100.0, 100.0));
+        let relative = layer
+            .add_child_window(
+                root,
+                positioned(
+                    CluiPositioning::Relative,
+                    Rect::from_values(5.0, 5.0, 20.0, 20.0),
+                    Offsets {
+                        left: Some(3.0),
+                        right: Some(100.0),
+                        bottom: Some(4.0),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        let child = layer
+            .add_child_window(relative, window_at(1.0, 1.0, 5.0, 5.0))
+            .unwrap();
+        layer.update();
+
+        // left wins over right, bottom moves up
+        assert_eq!(
+            layer.screen_rect(relative),
+            Some(Rect::from_values(18.0, 11.0, 20.0, 20.0))
+        );
+        assert_eq!(
+            layer.screen_rect(child),
+            Some(Rect::from_values(19.0, 12.0, 5.0, 5.0))
+        );
+    }
+
+    #[test]
+    fn absolute_against_positioned_ancestor() {
+        let mut layer = CluiLayer::new();
+        let card = layer.add_window(CluiUiRect {
+            rect: Rect::from_values(100.0, 100.0, 200.0, 100.0),
+            box_model: BoxModel {
+                padding: Padding::all(10.0),
+                ..Default::default()
+            },
+            positioning: CluiPositioning::Relative,
+            ..Default::default()
+        });
+        let row = layer
+            .add_child_window(card, window_at(20.0, 20.0, 50.0, 50.0))
+            .unwrap();
+        let badge = layer
+            .add_child_window(
+                row,
+                positioned(
+                    CluiPositioning::Absolute,
+                    Rect::from_values(0.0, 0.0, 16.0, 16.0),
+                    Offsets {
+                        top: Some(-8.0),
+                        right: Some(-8.0),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        let overlay = layer
+            .add_child_window(
+                row,
+                positioned(
+                    CluiPositioning::Absolute,
+                    Rect::from_values(0.0, 0.0, 16.0, 16.0),
+                    Offsets {
+                        left: Some(0.0),
+                        right: Some(0.0),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        layer.update();
+
+        // the static row is skipped, the card is the containing block
+        assert_eq!(
+            layer.screen_rect(badge),
+            Some(Rect::from_values(292.0, 92.0, 16.0, 16.0))
+        );
+        // stretched horizontally, left at its static position vertically
+        assert_eq!(
+            layer.screen_rect(overlay),
+            Some(Rect::from_values(100.0, 130.0, 200.0, 16.0))
+        );
+
+        // resizing the card moves the badge even though the row did not move
+        layer
+            .get_mut_window_by_key_unchecked(card)
+            .set_rect(Rect::from_values(100.0, 100.0, 300.0, 100.0));
+        layer.update();
+        assert_eq!(
+            layer.screen_rect(row),
+            Some(Rect::from_values(130.0, 130.0, 50.0, 50.0))
+        );
+        assert_eq!(
+            layer.screen_rect(badge),
+            Some(Rect::from_values(392.0, 92.0, 16.0, 16.0))
+        );
+    }
+
+    #[test]
+    fn absolute_without_positioned_ancestor_uses_viewport() {
+        let mut layer = CluiLayer::new();
+        layer.update_viewport(400.0, 300.0);
+        let panel = layer.add_window(window_at(50.0, 50.0, 100.0, 100.0));
+        layer
+            .get_mut_window_by_key_unchecked(panel)
+            .set_overflow(Overflow::Hidden);
+        let toast = layer
+            .add_child_window(
+                panel,
+                positioned(
+                    CluiPositioning::Absolute,
+                    Rect::from_values(0.0, 0.0, 100.0, 20.0),
+                    Offsets {
+                        right: Some(10.0),
+                        bottom: Some(10.0),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        layer.update();
+
+        assert_eq!(
+            layer.screen_rect(toast),
+            Some(Rect::from_values(290.0, 270.0, 100.0, 20.0))
+        );
+        // clipping comes from the containing block, not the hidden parent
+        assert_eq!(layer.windows[toast].clip, None);
+        assert!(layer.get_window_by_key_unchecked(toast).is_out_of_flow());
+    }
+
+    #[test]
+    fn canvas_scaling_modes() {
+        let reference = CanvasScaling::ScaleWithScreenSize {
+            reference: Size::from_wh(1920.0, 1080.0),
+            match_height: 0.0,
+        };
+        assert_eq!(reference.scale(Size::from_wh(960.0, 540.0)), 0.5);
+        assert_eq!(reference.scale(Size::from_wh(3840.0, 1080.0)), 2.0);
+        let blended = CanvasScaling::ScaleWithScreenSize {
+            reference: Size::from_wh(1920.0, 1080.0),
+            match_height: 0.5,
+        };
+        // halfway between 1 and 0.5 in log space
+        let scale = blended.scale(Size::from_wh(1920.0, 540.0));
+        assert!((scale - 0.5f32.sqrt()).abs() < 1e-5);
+
+        let physical = CanvasScaling::ConstantPhysicalSize {
+            units_per_inch: 48.0,
+        };
+        assert_eq!(physical.scale(Size::from_wh(100.0, 100.0)), 2.0);
+        assert_eq!(
+            CanvasScaling::ConstantPixelSize { scale: 0.0 }.scale(Size::default()),
+            1.0
+        );
+    }
+
+    #[test]
+    fn draw_list_scaled_from_reference_units() {
+        let mut layer = CluiLayer::new();
+        layer.set_canvas_scaling(CanvasScaling::ScaleWithScreenSize {
+            reference: Size::from_wh(1920.0, 1080.0),
+            match_height: 0.0,
+        });
+        layer.update_viewport(960.0, 540.0);
+        assert_eq!(layer.viewport(), Size::from_wh(1920.0, 1080.0));
+        assert_eq!(layer.pixel_viewport(), Size::from_wh(960.0, 540.0));
+
+        let panel = layer.add_window(window_at(1720.0, 980.0, 200.0, 100.0));
+        layer
+            .get_mut_window_by_key_unchecked(panel)
+            .set_overflow(Overflow::Hidden);
+        layer
+            .add_child_window(panel, window_at(0.0, 0.0, 20.0, 20.0))
+            .unwrap();
+        layer.update();
+        assert_eq!(
+            layer.screen_rect(panel),
+            Some(Rect::from_values(1720.0, 980.0, 200.0, 100.0))
+        );
+
+        let set = layer.build_draw_set();
+        assert_eq!(set.viewport, Size::from_wh(960.0, 540.0));
+        assert_eq!(set.scissor, Rect::from_values(0.0, 0.0, 960.0, 540.0));
+        assert!(set.vertices.iter().any(|v| v.position == [960.0, 540.0]));
+        assert!(set.vertices.iter().all(|v| v.position[0] >= 860.0));
+        assert_eq!(
+            set.draws[1].scissor,
+            Rect::from_values(860.0, 490.0, 100.0, 50.0)
+        );
+
+        // a bigger window keeps the same layout and scales up
+        layer.update_viewport(3840.0, 2160.0);
+        layer.update();
+        assert_eq!(layer.viewport(), Size::from_wh(1920.0, 1080.0));
+        assert!(layer
+            .build_draw_set()
+            .vertices
+            .iter()
+            .any(|v| v.position == [3840.0, 2160.0]));
+    }
+
+    #[test]
+    fn scale_factor_changes_output_not_layout() {
+        let mut layer = CluiLayer::new();
+        layer.update_viewport(400.0, 300.0);
+        let w = layer.add_window(window_at(10.0, 10.0, 100.0, 50.0));
+        layer.update();
+        let rebuilds = layer.geometry_rebuilds;
+
+        // moving to a 2x display
+        layer.set_scale_factor(2.0);
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, rebuilds + 1);
+        assert_eq!(layer.viewport(), Size::from_wh(400.0, 300.0));
+        assert_eq!(layer.physical_viewport(), Size::from_wh(800.0, 600.0));
+        assert_eq!(
+            layer.screen_rect(w),
+            Some(Rect::from_values(10.0, 10.0, 100.0, 50.0))
+        );
+
+        let set = layer.build_draw_set();
+        assert_eq!(set.viewport, Size::from_wh(800.0, 600.0));
+        assert!(set.vertices.iter().any(|v| v.position == [220.0, 120.0]));
+
+        // physical size canvases keep their layout size across densities
+        layer.set_canvas_scaling(CanvasScaling::ConstantPhysicalSize {
+            units_per_inch: 96.0,
+        });
+        assert_eq!(layer.viewport(), Size::from_wh(400.0, 300.0));
+        assert_eq!(layer.physical_scale(), 2.0);
+
+        layer.set_scale_factor(0.0);
+        assert_eq!(layer.scale_factor(), 1.0);
+    }
+
+    #[test]
+    fn text_drawn_in_content_box() {
+        let mut fonts = Fonts::new();
+        let font = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        let mut layer = CluiLayer::new();
+        let mut window = CluiUiRect::with_box_model(
+            Rect::from_values(10.0, 10.0, 200.0, 50.0),
+            BoxModel {
+                padding: Padding::all(5.0),
+                background_color: CluiColor {
+                    r: 1.0,
+                    g: 1.0,
+                    b: 1.0,
+                    a: 1.0,
+                },
+                ..Default::default()
+            },
+        );
+        window.set_text(Some(CluiText::new(
+            "Hi",
+            TextStyle {
+                font,
+                size: 20.0,
+                ..Default::default()
+            },
+        )));
+        let w = layer.add_window(window);
+        layer.update_with_fonts(&mut fonts);
+        assert_eq!(layer.text_layout(w).unwrap().glyphs.len(), 2);
+
+        // the background, then the glyphs from the first atlas page
+        let set = layer.build_draw_set();
+        assert_eq!(set.draws.len(), 2);
+        assert_eq!(set.draws[0].texture, None);
+        assert_eq!(set.draws[1].texture, Some(0));
+        assert_eq!(set.draws[1].index_count, 12);
+        let glyph_vertices = &set.vertices[set.draws[1].vertex_offset as usize..];
+        assert!(glyph_vertices
+            .iter()
+            .all(|v| v.position[0] >= 15.0 && v.position[1] >= 15.0 && v.position[0] <= 205.0));
+        assert!(glyph_vertices.iter().any(|v| v.uv != [0.0, 0.0]));
+
+        // text is laid out again only when the window or the fonts change
+        let rebuilds = layer.geometry_rebuilds;
+        layer.update_with_fonts(&mut fonts);
+        assert_eq!(layer.geometry_rebuilds, rebuilds);
+        fonts.clear_glyphs();
+        layer.update_with_fonts(&mut fonts);
+        assert_eq!(layer.geometry_rebuilds, rebuilds + 1);
+        layer
+            .get_mut_window_by_key_unchecked(w)
+            .text_mut()
+            .unwrap()
+            .text = "Hi there".to_string();
+        layer.update_with_fonts(&mut fonts);
+        assert_eq!(layer.text_layout(w).unwrap().glyphs.len(), 8);
+
+        // an update without fonts keeps the glyphs, and leaves unchanged windows alone
+        let rebuilds = layer.geometry_rebuilds;
+        layer.update();
+        layer.update();
+        assert_eq!(layer.geometry_rebuilds, rebuilds);
+        assert_eq!(layer.text_layout(w).unwrap().glyphs.len(), 8);
+        assert_eq!(layer.build_draw_set().draws.len(), 2);
+
+        // moving the window without fonts moves its glyphs, and lays them out again later
+        let before = layer.build_draw_set();
+        layer
+            .get_mut_window_by_key_unchecked(w)
+            .set_rect(Rect::from_values(20.0, 10.0, 200.0, 50.0));
+        layer.update();
+        let after = layer.build_draw_set();
+        let text_x = |set: &CluiDrawSet| set.vertices[set.draws[1].vertex_offset as usize].position;
+        assert_eq!(text_x(&after)[0], text_x(&before)[0] + 10.0);
+        assert_eq!(text_x(&after)[1], text_x(&before)[1]);
+        let rebuilds = layer.geometry_rebuilds;
+        layer.update_with_fonts(&mut fonts);
+        assert_eq!(layer.geometry_rebuilds, rebuilds + 1);
+        assert_eq!(text_x(&layer.build_draw_set()), text_x(&after));
+    }
+
+    #[test]
+    fn text_past_its_box_is_drawn() {
+        let mut fonts = Fonts::new();
+        let font = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        let mut layer = CluiLayer::new();
+        layer.update_viewport(400.0, 300.0);
+        let mut window = window_at(-300.0, 10.0, 200.0, 50.0);
+        let mut text = CluiText::new(
+            "A single line that runs far past the box it is in",
+            TextStyle {
+                font,
+                size: 20.0,
+                ..Default::default()
+            },
+        );
+        text.paragraph.wrap = false;
+        window.set_text(Some(text));
+        let w = layer.add_window(window);
+        layer.update_with_fonts(&mut fonts);
+
+        // the box is culled, the glyphs that reach the viewport are not
+        let set = layer.build_draw_set();
+        assert_eq!(set.draws.len(), 1);
+        assert_eq!(set.draws[0].texture, Some(0));
+
+        layer.get_mut_window_by_key_unchecked(w).set_text(None);
+        layer.update_with_fonts(&mut fonts);
+        assert!(layer.build_draw_set().draws.is_empty());
+    }
 }
@@ -0,0 +1,88 @@
+use crate::{Scalar, Size};
+
+/// A distance in one of several units, resolved to pixels during layout
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Length {
+    Px(Scalar),
+    /// Percent of a reference length given by the property, usually the parent's content size
+    Percent(Scalar),
+    /// Multiple of the font size of the element
+    Em(Scalar),
+    /// Multiple of the root font size
+    Rem(Scalar),
+    /// Percent of the viewport width
+    Vw(Scalar),
+    /// Percent of the viewport height
+    Vh(Scalar),
+}
+
+impl Default for Length {
+    fn default() -> Self {
+        Length::Px(0.0)
+    }
+}
+
+impl From<Scalar> for Length {
+    fn from(px: Scalar) -> Self {
+        Length::Px(px)
+    }
+}
+
+/// What relative lengths are measured against
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LengthContext {
+    pub font_size: Scalar,
+    pub root_font_size: Scalar,
+    pub viewport: Size,
+    /// Content size of the parent, which the percentages of a box model are of
+    pub parent_content: Size,
+}
+
+impl Default for LengthContext {
+    fn default() -> Self {
+        Self {
+            font_size: 16.0,
+            root_font_size: 16.0,
+            viewport: Size::default(),
+            parent_content: Size::default(),
+        }
+    }
+}
+
+impl Length {
+    /// The length in pixels, with `Percent` taken of `percent_of`
+    ///
+    pub fn resolve(&self, context: &LengthContext, percent_of: Scalar) -> Scalar {
+        match *self {
+            Length::Px(v) => v,
+            Length::Percent(v) => percent_of * v / 100.0,
+            Length::Em(v) => context.font_size * v,
+            Length::Rem(v) => context.root_font_size * v,
+            Length::Vw(v) => context.viewport.width * v / 100.0,
+            Length::Vh(v) => context.viewport.height * v / 100.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Length, LengthContext, Size};
+
+    #[test]
+    fn resolve_units() {
+        let context = LengthContext {
+            font_size: 20.0,
+            root_font_size: 10.0,
+            viewport: Size::from_wh(1920.0, 1080.0),
+            ..Default::default()
+        };
+        assert_eq!(Length::from(12.0).resolve(&context, 500.0), 12.0);
+        assert_eq!(Length::Percent(25.0).resolve(&context, 500.0), 125.0);
+        assert_eq!(Length::Em(1.5).resolve(&context, 500.0), 30.0);
+        assert_eq!(Length::Rem(1.5).resolve(&context, 500.0), 15.0);
+        assert_eq!(Length::Vw(50.0).resolve(&context, 500.0), 960.0);
+        assert_eq!(Length::Vh(10.0).resolve(&context, 500.0), 108.0);
+    }
+}
@@ -1,36 +1,56 @@
 #![allow(unused)]
 
-pub use self::rect::Rect;
+pub use self::rect::{Corner, Rect};
 
+mod atlas;
+mod draw_list;
+mod error;
+mod font;
+mod length;
+mod markup;
+mod point;
 pub mod rect;
 mod size;
+mod tessellate;
+mod text;
+mod transform;
 mod ui_box;
+pub use crate::atlas::{AtlasFormat, AtlasPage, AtlasRegion, AtlasUpdate, TextureAtlas};
+pub use crate::draw_list::{CluiDrawList, CluiDrawSet, CluiVertex, DrawInstruction};
+pub use crate::error::CluiError;
+pub use crate::font::{
+    AtlasGlyph, Font, FontCollection, FontCollectionKey, FontKey, FontMetrics, FontStyle, Fonts,
+};
+pub use crate::length::{Length, LengthContext};
+pub use crate::point::Point;
 pub use crate::size::*;
+pub use crate::tessellate::TessellationOptions;
+pub use crate::text::{
+    CluiText, InlineImage, LineHeight, ParagraphStyle, PositionedGlyph, PositionedImage, TextAlign,
+    TextDirection, TextLayout, TextLine, TextOverflow, TextSpan, TextStyle,
+};
+pub use crate::transform::Transform2D;
+pub use crate::ui_box::*;
 
-use crate::layer::CluiLayer;
+pub use crate::layer::{
+    CanvasScaling, CluiLayer, CluiUiRect, CluiWindowKey, CluiWindowMut, Overflow,
+};
 //use crate::size::Size;
 use slotmap::{new_key_type, SlotMap};
 
 pub type Scalar = f32;
 
-#[derive(Copy, Clone, Default, PartialEq, Debug)]
-pub struct Point {
-    x: Scalar,
-    y: Scalar,
-}
-
-impl Point {
-    pub fn from_xy(x: Scalar, y: Scalar) -> Point {
-        Point { x, y }
-    }
-}
+type LogMessageHandler<'a> = Box<dyn FnMut(&str) -> bool + 'a>;
+type FileReadHandler<'a> = Box<dyn FnMut(&str) -> Option<Vec<u8>> + 'a>;
 
-struct Clui<'a> {
+pub struct Clui<'a> {
     get_elapsed_time_callback: Option<Box<dyn Fn() -> f64 + 'a>>,
-    log_message_handler: Option<Box<dyn FnMut(&str) -> bool + 'a>>,
-    file_read_handler: Option<Box<dyn FnMut(&str) -> Vec<u8> + 'a>>,
+    log_message_handler: Option<LogMessageHandler<'a>>,
+    file_read_handler: Option<FileReadHandler<'a>>,
 
     layers: SlotMap<CluiLayerKey, CluiLayer>,
+    scale_factor: Scalar,
+    fonts: Fonts,
 }
 
 new_key_type! { pub struct CluiLayerKey; }
@@ -42,6 +62,8 @@ impl<'a> Clui<'a> {
             log_message_handler: None,
             file_read_handler: None,
             layers: SlotMap::with_key(),
+            scale_factor: 1.0,
+            fonts: Fonts::new(),
         }
     }
 
@@ -59,19 +81,167 @@ impl<'a> Clui<'a> {
         self.log_message_handler = Some(Box::new(func));
     }
 
+    /// Pass a message to the log handler. Returns false if there is no handler, otherwise
+    /// whatever the handler returns.
+    ///
+    pub fn log(&mut self, message: &str) -> bool {
+        self.log_message_handler
+            .as_mut()
+            .is_some_and(|handler| handler(message))
+    }
+
+    /// The handler returns `None` if the file can not be read
+    ///
     pub fn set_file_read_handler<F>(&mut self, func: F)
     where
-        F: FnMut(&str) -> Vec<u8> + 'a,
+        F: FnMut(&str) -> Option<Vec<u8>> + 'a,
     {
         self.file_read_handler = Some(Box::new(func));
     }
 
-    pub fn get_render_data() -> CluiDrawList {
-        todo!()
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, CluiError> {
+        self.file_read_handler
+            .as_mut()
+            .and_then(|handler| handler(path))
+            .ok_or_else(|| CluiError::FileRead(path.to_string()))
+    }
+
+    /// Load a TrueType or OpenType font through the file read handler. Loading a path that is
+    /// already loaded returns the existing font.
+    ///
+    pub fn load_font(&mut self, path: &str) -> Result<FontKey, CluiError> {
+        if let Some(key) = self.fonts.find_font(path) {
+            return Ok(key);
+        }
+        let data = self.read_file(path)?;
+        self.fonts.add_font(path, data)
+    }
+
+    /// Load the fonts of one style of a collection through the file read handler, the primary
+    /// font first and then its fallbacks. The collection is created the first time its name is
+    /// used, and later calls add or replace styles.
+    ///
+    pub fn load_font_collection(
+        &mut self,
+        name: &str,
+        style: FontStyle,
+        paths: &[&str],
+    ) -> Result<FontCollectionKey, CluiError> {
+        let fonts = paths
+            .iter()
+            .map(|path| self.load_font(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = match self.fonts.find_collection(name) {
+            Some(key) => key,
+            None => self.fonts.add_collection(FontCollection::new(name)),
+        };
+        if let Some(collection) = self.fonts.collection_mut(key) {
+            collection.set_fonts(style, fonts);
+        }
+        Ok(key)
+    }
+
+    /// Load a PNG image through the file read handler, to be drawn in text as `name`. Loading
+    /// a name again replaces the image.
+    ///
+    pub fn load_image(&mut self, name: &str, path: &str) -> Result<(), CluiError> {
+        let data = self.read_file(path)?;
+        let (width, height, texels) =
+            font::decode_png(&data).ok_or_else(|| CluiError::InvalidImage(path.to_string()))?;
+        self.fonts
+            .add_image(name, width, height, texels)
+            .map(|_| ())
+            .ok_or_else(|| CluiError::InvalidImage(path.to_string()))
+    }
+
+    pub fn fonts(&self) -> &Fonts {
+        &self.fonts
+    }
+
+    pub fn fonts_mut(&mut self) -> &mut Fonts {
+        &mut self.fonts
+    }
+
+    /// The glyph atlas regions to upload before rendering the next frame
+    ///
+    pub fn take_atlas_updates(&mut self) -> Vec<AtlasUpdate> {
+        self.fonts.take_atlas_updates()
+    }
+
+    /// Update every layer, laying out text with the loaded fonts. Call this before
+    /// `take_atlas_updates` and `get_render_data`, as new glyphs are added to the atlas here.
+    ///
+    /// If the atlas fills up and is emptied part way through, the layers are updated again, so
+    /// every layer draws from the new atlas. When the glyphs of one frame do not fit in the
+    /// atlas at all, its page limit is doubled and the change is logged.
+    ///
+    pub fn update(&mut self) {
+        let mut emptied = false;
+        loop {
+            let generation = self.fonts.generation();
+            for layer in self.layers.values_mut() {
+                layer.update_with_fonts(&mut self.fonts);
+            }
+            if self.fonts.generation() == generation {
+                break;
+            }
+
+            // emptied twice in a row, so the glyphs in use need more pages than are allowed
+            if emptied {
+                if let Some(max_pages) = self.fonts.max_atlas_pages() {
+                    self.fonts.set_max_atlas_pages(Some(max_pages * 2));
+                    self.log(&format!(
+                        "clui: the glyphs of one frame need more than {} atlas pages, allowing {}",
+                        max_pages,
+                        max_pages * 2
+                    ));
+                }
+            }
+            emptied = true;
+        }
+    }
+
+    /// A layer along with the fonts, for code that measures text while changing the layer
+    ///
+    pub fn get_layer_and_fonts(&mut self, key: CluiLayerKey) -> Option<(&mut CluiLayer, &Fonts)> {
+        Some((self.layers.get_mut(key)?, &self.fonts))
+    }
+
+    /// Build the vertex and index data for every layer, one draw set per layer
+    ///
+    pub fn get_render_data(&self) -> CluiDrawList {
+        CluiDrawList {
+            draw_sets: self
+                .layers
+                .values()
+                .map(|layer| layer.build_draw_set())
+                .collect(),
+        }
+    }
+
+    /// Set the number of physical pixels per logical pixel for every layer, and for layers
+    /// created later. Call this when the window moves to a display with a different density.
+    /// Glyphs rasterized for the old scale factor are dropped from the atlas.
+    ///
+    pub fn set_scale_factor(&mut self, scale_factor: Scalar) {
+        let scale_factor = layer::valid_scale_factor(scale_factor);
+        if scale_factor != self.scale_factor {
+            self.fonts.clear_glyphs();
+        }
+        self.scale_factor = scale_factor;
+        for layer in self.layers.values_mut() {
+            layer.set_scale_factor(scale_factor);
+        }
+    }
+
+    pub fn scale_factor(&self) -> Scalar {
+        self.scale_factor
     }
 
     pub fn create_layer(&mut self) -> CluiLayerKey {
-        self.layers.insert(CluiLayer::new())
+        let mut layer = CluiLayer::new();
+        layer.set_scale_factor(self.scale_factor);
+        self.layers.insert(layer)
     }
 
     pub fn get_layer_by_key(&mut self, key: CluiLayerKey) -> Option<&mut CluiLayer> {
@@ -79,6 +249,12 @@ impl<'a> Clui<'a> {
     }
 }
 
+impl<'a> Default for Clui<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 mod layer;
 
 struct CluiWidget {}
@@ -91,43 +267,36 @@ pub struct CluiColor {
     pub a: f32,
 }
 
-#[derive(Copy, Clone)]
-enum CluiPositioning {
+/// How a window is placed relative to the normal flow of its parent
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum CluiPositioning {
+    /// Placed by its rect, or by the parent's layout
+    #[default]
+    Static,
+    /// Placed as `Static`, then moved by its offsets. Its children are unaffected by the move
+    /// relative to it, and it becomes the containing block of absolute descendants.
     Relative,
+    /// Taken out of the flow and placed by its offsets against the padding box of the nearest
+    /// `Relative` or `Absolute` ancestor, or the layer viewport if there is none
     Absolute,
 }
 
-#[derive(Copy, Clone)]
-struct CluiVertex {
-    position: [f32; 2],
-    color: [f32; 4],
-}
-
-#[derive(Clone)]
-struct CluiDrawList {
-    draw_sets: Vec<CluiDrawSet>,
-}
-
-#[derive(Clone)]
-struct CluiDrawSet {
-    viewport: Size,
-    scissor: Rect,
-    draws: Vec<DrawInstruction>,
-    vertices: Vec<CluiVertex>,
-    indices: Vec<u32>,
-}
-
-#[derive(Clone)]
-struct DrawInstruction {
-    index_offset: u32,
-    index_count: u32,
-    vertex_offset: u32,
+/// Distances from the edges of the containing block, used by positioned windows. `None` leaves
+/// that edge to the window's rect.
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Offsets {
+    pub left: Option<Scalar>,
+    pub right: Option<Scalar>,
+    pub top: Option<Scalar>,
+    pub bottom: Option<Scalar>,
 }
 
 #[cfg(test)]
 mod tests {
     use crate::layer::CluiUiRect;
-    use crate::{Clui, CluiColor, Rect, Size};
+    use crate::{Clui, CluiColor, CluiError, CluiText, FontStyle, Rect, Size, TextStyle};
 
     #[test]
     fn layer_basics() {
@@ -141,14 +310,15 @@ mod tests {
             let rect = Rect::from_values(10.0, 11.0, 100.0, 110.0);
             let wk = layer.add_window(CluiUiRect {
                 rect,
-                background_color: Default::default(),
+                box_model: Default::default(),
                 z_index: 0,
+                ..Default::default()
             });
-            let w = layer.get_window_by_key(wk);
+            let w = layer.get_window_by_key(wk).unwrap();
 
             assert_eq!(w.rect, Rect::from_values(10.0, 11.0, 100.0, 110.0));
             assert_eq!(
-                w.background_color,
+                w.background_color(),
                 CluiColor {
                     r: 0.0,
                     g: 0.0,
@@ -158,6 +328,246 @@ mod tests {
             );
 
             layer.remove_window(wk);
+            assert!(layer.get_window_by_key(wk).is_none());
+        }
+    }
+
+    #[test]
+    fn read_file_errors() {
+        let mut clui = crate::Clui::new();
+        assert_eq!(
+            clui.read_file("font.ttf"),
+            Err(CluiError::FileRead("font.ttf".to_string()))
+        );
+
+        clui.set_file_read_handler(|path| match path {
+            "font.ttf" => Some(vec![1, 2, 3]),
+            _ => None,
+        });
+        assert_eq!(clui.read_file("font.ttf"), Ok(vec![1, 2, 3]));
+        assert!(clui.read_file("missing.ttf").is_err());
+    }
+
+    #[test]
+    fn log_messages() {
+        let mut messages = Vec::new();
+        {
+            let mut clui = crate::Clui::new();
+            assert!(!clui.log("dropped"));
+            clui.set_log_message_handler(|message| {
+                messages.push(message.to_string());
+                true
+            });
+            assert!(clui.log("kept"));
+        }
+        assert_eq!(messages, ["kept"]);
+    }
+
+    #[test]
+    fn load_font_through_handler() {
+        let mut clui = Clui::new();
+        let mut reads = 0;
+        clui.set_file_read_handler(|path| {
+            reads += 1;
+            match path {
+                "fonts/sans.ttf" => Some(crate::font::test_font_data()),
+                "fonts/broken.ttf" => Some(vec![1, 2, 3]),
+                _ => None,
+            }
+        });
+        let key = clui.load_font("fonts/sans.ttf").unwrap();
+        assert_eq!(clui.load_font("fonts/sans.ttf"), Ok(key));
+        assert_eq!(
+            clui.load_font("fonts/broken.ttf"),
+            Err(CluiError::InvalidFont("fonts/broken.ttf".to_string()))
+        );
+        assert_eq!(
+            clui.load_font("fonts/missing.ttf"),
+            Err(CluiError::FileRead("fonts/missing.ttf".to_string()))
+        );
+
+        let glyph = clui.fonts().font(key).unwrap().glyph_id('g').unwrap();
+        assert!(clui.fonts_mut().glyph(key, glyph, 16.0).is_some());
+        assert_eq!(clui.take_atlas_updates().len(), 1);
+        drop(clui);
+        assert_eq!(reads, 3);
+    }
+
+    #[test]
+    fn load_font_collection_through_handler() {
+        let mut clui = Clui::new();
+        clui.set_file_read_handler(|path| match path {
+            "fonts/light.ttf" => Some(crate::font::test_light_font_data()),
+            "fonts/sans.ttf" => Some(crate::font::test_font_data()),
+            _ => None,
+        });
+        let key = clui
+            .load_font_collection(
+                "ui",
+                FontStyle::Regular,
+                &["fonts/light.ttf", "fonts/sans.ttf"],
+            )
+            .unwrap();
+        let bold = clui
+            .load_font_collection("ui", FontStyle::Bold, &["fonts/sans.ttf"])
+            .unwrap();
+        assert_eq!(bold, key);
+        assert_eq!(
+            clui.load_font_collection("ui", FontStyle::Italic, &["fonts/missing.ttf"]),
+            Err(CluiError::FileRead("fonts/missing.ttf".to_string()))
+        );
+
+        let light = clui.fonts().find_font("fonts/light.ttf").unwrap();
+        let sans = clui.fonts().find_font("fonts/sans.ttf").unwrap();
+        let collection = clui.fonts().collection(key).unwrap();
+        assert_eq!(collection.fonts(FontStyle::Regular), [light, sans]);
+        assert_eq!(collection.fonts(FontStyle::Bold), [sans]);
+        assert_eq!(collection.fonts(FontStyle::Italic), [light, sans]);
+    }
+
+    #[test]
+    fn load_image_through_handler() {
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, 3, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[200; 18]).unwrap();
+        writer.finish().unwrap();
+
+        let mut clui = Clui::new();
+        clui.set_file_read_handler(move |path| match path {
+            "icons/fire.png" => Some(png_data.clone()),
+            "icons/broken.png" => Some(vec![1, 2, 3]),
+            _ => None,
+        });
+        clui.load_image("fire", "icons/fire.png").unwrap();
+        let image = clui.fonts().image("fire").unwrap();
+        assert_eq!((image.region.width, image.region.height), (3, 2));
+        assert_eq!(
+            clui.load_image("fire", "icons/broken.png"),
+            Err(CluiError::InvalidImage("icons/broken.png".to_string()))
+        );
+        assert_eq!(
+            clui.load_image("fire", "icons/missing.png"),
+            Err(CluiError::FileRead("icons/missing.png".to_string()))
+        );
+    }
+
+    #[test]
+    fn scale_factor_reaches_layers() {
+        let mut clui = crate::Clui::new();
+        let k1 = clui.create_layer();
+        clui.set_scale_factor(1.5);
+        let k2 = clui.create_layer();
+        assert_eq!(clui.get_layer_by_key(k1).unwrap().scale_factor(), 1.5);
+        assert_eq!(clui.get_layer_by_key(k2).unwrap().scale_factor(), 1.5);
+        assert_eq!(clui.scale_factor(), 1.5);
+
+        clui.set_scale_factor(f32::NAN);
+        assert_eq!(clui.scale_factor(), 1.0);
+        assert_eq!(clui.get_layer_by_key(k1).unwrap().scale_factor(), 1.0);
+    }
+
+    #[test]
+    fn render_data_per_layer() {
+        let mut clui = crate::Clui::new();
+        let k1 = clui.create_layer();
+        let k2 = clui.create_layer();
+        {
+            let layer = clui.get_layer_by_key(k1).unwrap();
+            layer.update_viewport(1024.0, 768.0);
+            layer.add_window(CluiUiRect::new(
+                Rect::from_values(10.0, 20.0, 100.0, 50.0),
+                CluiColor {
+                    r: 1.0,
+                    g: 0.5,
+                    b: 0.25,
+                    a: 1.0,
+                },
+            ));
+            layer.add_default_window();
+            layer.update();
+        }
+
+        let draw_list = clui.get_render_data();
+        assert_eq!(draw_list.draw_sets.len(), 2);
+
+        let set = draw_list
+            .draw_sets
+            .iter()
+            .find(|s| s.viewport == Size::from_wh(1024.0, 768.0))
+            .unwrap();
+        assert_eq!(set.scissor, Rect::from_values(0.0, 0.0, 1024.0, 768.0));
+        // the default window is transparent and draws nothing
+        assert_eq!(set.vertices.len(), 4);
+        assert_eq!(set.indices.len(), 6);
+        assert_eq!(set.draws.len(), 1);
+        assert_eq!(set.draws[0].index_count, 6);
+
+        let v = set
+            .vertices
+            .iter()
+            .find(|v| v.position == [110.0, 70.0])
+            .unwrap();
+        assert_eq!(v.color, [1.0, 0.5, 0.25, 1.0]);
+
+        let empty = draw_list
+            .draw_sets
+            .iter()
+            .find(|s| s.viewport == Size::from_wh(800.0, 600.0))
+            .unwrap();
+        assert!(empty.vertices.is_empty());
+        assert!(empty.draws.is_empty());
+    }
+
+    #[test]
+    fn atlas_limit_grows_to_fit_a_frame() {
+        let mut messages = Vec::new();
+        {
+            let mut clui = Clui::new();
+            clui.set_log_message_handler(|message| {
+                messages.push(message.to_string());
+                true
+            });
+            let font = clui
+                .fonts_mut()
+                .add_font("sans.ttf", crate::font::test_font_data())
+                .unwrap();
+            clui.fonts_mut().set_atlas_page_size(64);
+            clui.fonts_mut().set_max_atlas_pages(Some(2));
+
+            // each size takes a page of its own, one more than allowed
+            let layer_key = clui.create_layer();
+            let layer = clui.get_layer_by_key(layer_key).unwrap();
+            for size in [61.0, 62.0, 63.0] {
+                let mut window = CluiUiRect::new(
+                    Rect::from_values(0.0, 0.0, 100.0, 100.0),
+                    CluiColor::default(),
+                );
+                window.set_text(Some(CluiText::new(
+                    "A",
+                    TextStyle {
+                        font,
+                        size,
+                        ..Default::default()
+                    },
+                )));
+                layer.add_window(window);
+            }
+
+            clui.update();
+            assert_eq!(clui.fonts().max_atlas_pages(), Some(4));
+            assert_eq!(clui.fonts().atlas().pages().len(), 3);
+            // the glyphs drawn are the ones in the atlas now
+            let generation = clui.fonts().generation();
+            clui.update();
+            assert_eq!(clui.fonts().generation(), generation);
+            let set = &clui.get_render_data().draw_sets[0];
+            assert_eq!(set.draws.len(), 3);
         }
+        assert_eq!(
+            messages,
+            ["clui: the glyphs of one frame need more than 2 atlas pages, allowing 4"]
+        );
     }
 }
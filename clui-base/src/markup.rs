@@ -0,0 +1,306 @@
+use crate::font::{FontStyle, Fonts};
+use crate::text::{CluiText, InlineImage, TextStyle};
+use crate::{CluiColor, CluiError, Scalar};
+
+/// Parse rich text markup into a `CluiText`. See `CluiText::from_markup` for the tags.
+///
+pub(crate) fn parse(markup: &str, style: TextStyle, fonts: &Fonts) -> Result<CluiText, CluiError> {
+    let mut text = CluiText::new("", style);
+    // open tags, with the style to go back to when each closes
+    let mut open: Vec<(&str, TextStyle)> = Vec::new();
+    let mut current = style;
+    let mut run = String::new();
+
+    let mut rest = markup;
+    while let Some(i) = rest.find(['<', '&']) {
+        run.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with('&') {
+            let (c, len) = [("&lt;", '<'), ("&gt;", '>'), ("&amp;", '&')]
+                .iter()
+                .find(|(entity, _)| rest.starts_with(entity))
+                .map(|(entity, c)| (*c, entity.len()))
+                .ok_or_else(|| error(markup, rest, "unknown entity"))?;
+            run.push(c);
+            rest = &rest[len..];
+            continue;
+        }
+
+        let end = rest
+            .find('>')
+            .ok_or_else(|| error(markup, rest, "unterminated tag"))?;
+        let tag = &rest[1..end];
+        let at = rest;
+        rest = &rest[end + 1..];
+        flush(&mut text, &mut run, &current, &style);
+
+        if let Some(name) = tag.strip_prefix('/') {
+            match open.pop() {
+                Some((opened, previous)) if opened == name.trim() => current = previous,
+                Some((opened, _)) => {
+                    return Err(error(markup, at, &format!("expected </{}>", opened)));
+                }
+                None => return Err(error(markup, at, "closing tag with no open tag")),
+            }
+            continue;
+        }
+
+        let (tag, self_closing) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let (name, value) = match tag.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (tag.trim(), None),
+        };
+
+        if name == "icon" {
+            let value = value.ok_or_else(|| error(markup, at, "icon needs a name"))?;
+            if !self_closing {
+                return Err(error(
+                    markup,
+                    at,
+                    "icon tags close themselves, as <icon=name/>",
+                ));
+            }
+            text.push_image(
+                InlineImage {
+                    name: value.to_string(),
+                    size: None,
+                },
+                current,
+            );
+            continue;
+        }
+        if self_closing {
+            return Err(error(markup, at, "only icon tags close themselves"));
+        }
+
+        if matches!(name, "b" | "i") && current.collection.is_none() {
+            return Err(error(markup, at, "bold and italic need a font collection"));
+        }
+        let mut styled = current;
+        match (name, value) {
+            ("b", None) => {
+                styled.font_style = FontStyle::from_flags(true, current.font_style.is_italic())
+            }
+            ("i", None) => {
+                styled.font_style = FontStyle::from_flags(current.font_style.is_bold(), true)
+            }
+            ("color", Some(value)) => {
+                styled.color =
+                    parse_color(value).ok_or_else(|| error(markup, at, "invalid color"))?
+            }
+            ("size", Some(value)) => {
+                styled.size = value
+                    .parse::<Scalar>()
+                    .ok()
+                    .filter(|size| size.is_finite() && *size > 0.0)
+                    .ok_or_else(|| error(markup, at, "invalid size"))?
+            }
+            ("font", Some(value)) => {
+                if let Some(collection) = fonts.find_collection(value) {
+                    styled.collection = Some(collection);
+                } else if let Some(font) = fonts.find_font(value) {
+                    styled.collection = None;
+                    styled.font = font;
+                } else {
+                    return Err(error(markup, at, "unknown font"));
+                }
+            }
+            _ => return Err(error(markup, at, "unknown tag")),
+        }
+        open.push((name, current));
+        current = styled;
+    }
+    run.push_str(rest);
+    flush(&mut text, &mut run, &current, &style);
+
+    match open.last() {
+        Some((name, _)) => Err(CluiError::InvalidMarkup(format!(
+            "<{}> is not closed",
+            name
+        ))),
+        None => Ok(text),
+    }
+}
+
+// Append the text gathered so far, as a span if it is styled
+fn flush(text: &mut CluiText, run: &mut String, current: &TextStyle, base: &TextStyle) {
+    if run.is_empty() {
+        return;
+    }
+    if current == base {
+        text.text.push_str(run);
+    } else {
+        text.push(run, *current);
+    }
+    run.clear();
+}
+
+fn error(markup: &str, at: &str, message: &str) -> CluiError {
+    let offset = markup.len() - at.len();
+    CluiError::InvalidMarkup(format!("{} at byte {}", message, offset))
+}
+
+/// A color by name, or as `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+///
+fn parse_color(value: &str) -> Option<CluiColor> {
+    let rgb = |r: u8, g: u8, b: u8| [r, g, b, 255];
+    let bytes = match value.to_ascii_lowercase().as_str() {
+        "black" => rgb(0, 0, 0),
+        "white" => rgb(255, 255, 255),
+        "red" => rgb(255, 0, 0),
+        "green" => rgb(0, 128, 0),
+        "lime" => rgb(0, 255, 0),
+        "blue" => rgb(0, 0, 255),
+        "yellow" => rgb(255, 255, 0),
+        "orange" => rgb(255, 165, 0),
+        "purple" => rgb(128, 0, 128),
+        "cyan" => rgb(0, 255, 255),
+        "magenta" => rgb(255, 0, 255),
+        "gray" | "grey" => rgb(128, 128, 128),
+        "transparent" => [0, 0, 0, 0],
+        hex => {
+            let digits = hex.strip_prefix('#')?;
+            if !digits.is_ascii() {
+                return None;
+            }
+            let channel = |i: usize, width: usize| {
+                let v = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16).ok()?;
+                Some(if width == 1 { v * 17 } else { v })
+            };
+            let (count, width) = match digits.len() {
+                3 => (3, 1),
+                4 => (4, 1),
+                6 => (3, 2),
+                8 => (4, 2),
+                _ => return None,
+            };
+            let mut bytes = [255; 4];
+            for (i, byte) in bytes.iter_mut().enumerate().take(count) {
+                *byte = channel(i, width)?;
+            }
+            bytes
+        }
+    };
+    let [r, g, b, a] = bytes.map(|c| c as f32 / 255.0);
+    Some(CluiColor { r, g, b, a })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::font::{test_font_data, FontCollection, FontStyle, Fonts};
+    use crate::markup::parse_color;
+    use crate::text::{CluiText, TextStyle};
+    use crate::{CluiColor, CluiError};
+
+    #[test]
+    fn spans_and_icons() {
+        let mut fonts = Fonts::new();
+        let font = fonts.add_font("sans.ttf", test_font_data()).unwrap();
+        let style = TextStyle {
+            font,
+            ..Default::default()
+        };
+        let text = CluiText::from_markup(
+            "Deals <color=red>50</color> fire damage <icon=fire/>",
+            style,
+            &fonts,
+        )
+        .unwrap();
+        assert_eq!(text.text, "Deals 50 fire damage \u{fffc}");
+        assert_eq!(text.spans.len(), 2);
+        assert_eq!(text.spans[0].range, 6..8);
+        assert_eq!(text.spans[0].style.color, parse_color("#f00").unwrap());
+        assert_eq!(text.spans[1].range, 21..24);
+        assert_eq!(text.spans[1].image.as_ref().unwrap().name, "fire");
+        assert_eq!(text.spans[1].style, style);
+
+        // nested tags stack, and close back to the outer style
+        let bold = fonts.add_font("bold.ttf", test_font_data()).unwrap();
+        let mut collection = FontCollection::new("ui");
+        collection.set_fonts(FontStyle::Regular, vec![font]);
+        collection.set_fonts(FontStyle::Bold, vec![bold]);
+        fonts.add_collection(collection);
+        let text = CluiText::from_markup(
+            "<font=ui><b>a<i><size=30>b</size></i></b>c</font>&lt;d&gt;",
+            style,
+            &fonts,
+        )
+        .unwrap();
+        assert_eq!(text.text, "abc<d>");
+        let styles: Vec<(FontStyle, f32)> = text
+            .spans
+            .iter()
+            .map(|span| (span.style.font_style, span.style.size))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                (FontStyle::Bold, 16.0),
+                (FontStyle::BoldItalic, 30.0),
+                (FontStyle::Regular, 16.0)
+            ]
+        );
+        assert!(text.spans[0].style.collection.is_some());
+        assert_eq!(
+            text.spans
+                .iter()
+                .map(|s| s.range.clone())
+                .collect::<Vec<_>>(),
+            [0..1, 1..2, 2..3]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let fonts = Fonts::new();
+        let style = TextStyle::default();
+        let parse = |markup| CluiText::from_markup(markup, style, &fonts).err();
+        let invalid = |message: &str| Some(CluiError::InvalidMarkup(message.to_string()));
+
+        assert_eq!(parse("a <size=2>b"), invalid("<size> is not closed"));
+        assert_eq!(
+            parse("<size=2><color=red></size></color>"),
+            invalid("expected </color> at byte 19")
+        );
+        assert_eq!(
+            parse("</b>"),
+            invalid("closing tag with no open tag at byte 0")
+        );
+        assert_eq!(
+            parse("a <i>b</i>"),
+            invalid("bold and italic need a font collection at byte 2")
+        );
+        assert_eq!(parse("<blink>x</blink>"), invalid("unknown tag at byte 0"));
+        assert_eq!(parse("<color=plaid>"), invalid("invalid color at byte 0"));
+        assert_eq!(parse("<size=-2>"), invalid("invalid size at byte 0"));
+        assert_eq!(parse("<font=serif>"), invalid("unknown font at byte 0"));
+        assert_eq!(
+            parse("<icon=fire>"),
+            invalid("icon tags close themselves, as <icon=name/> at byte 0")
+        );
+        assert_eq!(parse("a < b"), invalid("unterminated tag at byte 2"));
+        assert_eq!(parse("a & b"), invalid("unknown entity at byte 2"));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            parse_color("#ff000080"),
+            Some(CluiColor {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 128.0 / 255.0
+            })
+        );
+        assert_eq!(parse_color("#fff"), parse_color("White"));
+        assert_eq!(parse_color("#0000"), parse_color("transparent"));
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#gggggg"), None);
+        assert_eq!(parse_color("#ééé"), None);
+    }
+}
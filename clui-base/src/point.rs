@@ -0,0 +1,101 @@
+use crate::Scalar;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Point {
+    pub x: Scalar,
+    pub y: Scalar,
+}
+
+impl Point {
+    pub fn from_xy(x: Scalar, y: Scalar) -> Point {
+        Point { x, y }
+    }
+
+    pub fn dot(&self, other: Point) -> Scalar {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(&self) -> Scalar {
+        self.dot(*self).sqrt()
+    }
+
+    /// Linear interpolation, returning `self` at 0 and `other` at 1
+    ///
+    pub fn lerp(&self, other: Point, t: Scalar) -> Point {
+        *self + (other - *self) * t
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, rhs: Point) -> Point {
+        Point::from_xy(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign for Point {
+    fn add_assign(&mut self, rhs: Point) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, rhs: Point) -> Point {
+        Point::from_xy(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign for Point {
+    fn sub_assign(&mut self, rhs: Point) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<Scalar> for Point {
+    type Output = Point;
+
+    fn mul(self, rhs: Scalar) -> Point {
+        Point::from_xy(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl Div<Scalar> for Point {
+    type Output = Point;
+
+    fn div(self, rhs: Scalar) -> Point {
+        Point::from_xy(self.x / rhs, self.y / rhs)
+    }
+}
+
+impl Neg for Point {
+    type Output = Point;
+
+    fn neg(self) -> Point {
+        Point::from_xy(-self.x, -self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Point;
+
+    #[test]
+    fn point_arithmetic() {
+        let mut p = Point::from_xy(1.0, 2.0) + Point::from_xy(3.0, 4.0);
+        assert_eq!(p, Point::from_xy(4.0, 6.0));
+        p -= Point::from_xy(1.0, 1.0);
+        assert_eq!(p, Point::from_xy(3.0, 5.0));
+        assert_eq!(p * 2.0, Point::from_xy(6.0, 10.0));
+        assert_eq!(p / 2.0, Point::from_xy(1.5, 2.5));
+        assert_eq!(-p, Point::from_xy(-3.0, -5.0));
+        assert_eq!(Point::from_xy(3.0, 4.0).length(), 5.0);
+        assert_eq!(
+            Point::from_xy(0.0, 0.0).lerp(Point::from_xy(10.0, 20.0), 0.25),
+            Point::from_xy(2.5, 5.0)
+        );
+    }
+}
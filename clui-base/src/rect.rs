@@ -1,4 +1,4 @@
-use crate::{Point, Scalar, Size};
+use crate::{Padding, Point, Scalar, Size};
 
 #[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct Rect {
@@ -6,6 +6,7 @@ pub struct Rect {
     pub size: Size,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Corner {
     TopLeft,
     TopRight,
@@ -50,6 +51,74 @@ impl Rect {
         Self::from_corners(p1, p2)
     }
 
+    /// The area covered by both rects, or `None` if they do not touch
+    ///
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let left = Scalar::max(self.left(), other.left());
+        let top = Scalar::max(self.top(), other.top());
+        let right = Scalar::min(self.right(), other.right());
+        let bottom = Scalar::min(self.bottom(), other.bottom());
+
+        if right < left || bottom < top {
+            None
+        } else {
+            Some(Self::from_values(left, top, right - left, bottom - top))
+        }
+    }
+
+    /// True if the rects share some area. Rects that only touch along an edge do not overlap.
+    ///
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.left() < other.right()
+            && other.left() < self.right()
+            && self.top() < other.bottom()
+            && other.top() < self.bottom()
+    }
+
+    /// True if the rect has no area
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.size.is_empty()
+    }
+
+    /// Shrink the rect by `padding` on each side, stopping at zero size
+    ///
+    pub fn inset(&self, padding: &Padding) -> Self {
+        Self::from_values(
+            self.left() + padding.left,
+            self.top() + padding.top,
+            Scalar::max(self.width() - padding.left - padding.right, 0.0),
+            Scalar::max(self.height() - padding.top - padding.bottom, 0.0),
+        )
+    }
+
+    /// Grow the rect by `padding` on each side
+    ///
+    pub fn outset(&self, padding: &Padding) -> Self {
+        Self::from_values(
+            self.left() - padding.left,
+            self.top() - padding.top,
+            self.width() + padding.left + padding.right,
+            self.height() + padding.top + padding.bottom,
+        )
+    }
+
+    /// The closest point inside the rect
+    ///
+    pub fn clamp_point(&self, p: Point) -> Point {
+        Point::from_xy(
+            p.x.clamp(self.left(), self.right()),
+            p.y.clamp(self.top(), self.bottom()),
+        )
+    }
+
+    pub fn translate(&self, offset: Point) -> Self {
+        Self {
+            point: self.point + offset,
+            ..*self
+        }
+    }
+
     pub fn move_to(&self, point: Point) -> Self {
         Self { point, ..*self }
     }
@@ -110,11 +179,22 @@ impl Rect {
     pub fn width(&self) -> Scalar {
         self.size.width
     }
+
+    /// Round each edge to the nearest physical pixel, where `scale` is physical pixels per unit.
+    /// Edges are rounded independently, so rects sharing an edge still share it afterwards.
+    ///
+    pub fn snap_to_pixels(&self, scale: Scalar) -> Self {
+        let snap = |v: Scalar| (v * scale).round() / scale;
+        Self::from_corners(
+            Point::from_xy(snap(self.left()), snap(self.top())),
+            Point::from_xy(snap(self.right()), snap(self.bottom())),
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Point, Rect, Size};
+    use crate::{Padding, Point, Rect, Size};
 
     #[test]
     fn has_rect_create() {
@@ -152,11 +232,11 @@ mod tests {
                 height: 20.0,
             },
         );
-        assert_eq!(r.contains_point(Point { x: 0.0, y: 0.0 }), false);
-        assert_eq!(r.contains_point(Point { x: 6.0, y: 11.0 }), true);
-        assert_eq!(r.contains_point(Point { x: 6.0, y: 0.0 }), false);
-        assert_eq!(r.contains_point(Point { x: 6.0, y: 50.0 }), false);
-        assert_eq!(r.contains_point(Point { x: 36.0, y: 11.0 }), false);
+        assert!(!r.contains_point(Point { x: 0.0, y: 0.0 }));
+        assert!(r.contains_point(Point { x: 6.0, y: 11.0 }));
+        assert!(!r.contains_point(Point { x: 6.0, y: 0.0 }));
+        assert!(!r.contains_point(Point { x: 6.0, y: 50.0 }));
+        assert!(!r.contains_point(Point { x: 36.0, y: 11.0 }));
     }
 
     #[test]
@@ -170,4 +250,80 @@ mod tests {
         assert_eq!(r3.height(), 23.0);
         assert_eq!(r3.center(), Point { x: 17.0, y: 16.5 });
     }
+
+    #[test]
+    fn rect_intersect() {
+        let r1 = Rect::from_values(0.0, 0.0, 10.0, 10.0);
+        let r2 = Rect::from_values(5.0, 6.0, 10.0, 10.0);
+        let r3 = Rect::from_values(20.0, 0.0, 10.0, 10.0);
+
+        assert_eq!(
+            r1.intersect(&r2),
+            Some(Rect::from_values(5.0, 6.0, 5.0, 4.0))
+        );
+        assert_eq!(r2.intersect(&r1), r1.intersect(&r2));
+        assert_eq!(r1.intersect(&r3), None);
+    }
+
+    #[test]
+    fn rect_overlaps() {
+        let r1 = Rect::from_values(0.0, 0.0, 10.0, 10.0);
+        assert!(r1.overlaps(&Rect::from_values(9.0, 9.0, 10.0, 10.0)));
+        assert!(!r1.overlaps(&Rect::from_values(10.0, 0.0, 10.0, 10.0)));
+        assert!(!r1.overlaps(&Rect::from_values(0.0, 20.0, 10.0, 10.0)));
+        assert!(r1.overlaps(&Rect::from_values(2.0, 2.0, 1.0, 1.0)));
+        assert!(!r1.is_empty());
+        assert!(Rect::from_values(5.0, 5.0, 0.0, 10.0).is_empty());
+    }
+
+    #[test]
+    fn rect_inset_outset() {
+        let r = Rect::from_values(10.0, 10.0, 100.0, 50.0);
+        let padding = Padding {
+            left: 1.0,
+            right: 2.0,
+            top: 3.0,
+            bottom: 4.0,
+        };
+        assert_eq!(r.inset(&padding), Rect::from_values(11.0, 13.0, 97.0, 43.0));
+        assert_eq!(r.outset(&padding), Rect::from_values(9.0, 7.0, 103.0, 57.0));
+        assert_eq!(r.outset(&padding).inset(&padding), r);
+        assert_eq!(r.inset(&Padding::all(60.0)).size, Size::from_wh(0.0, 0.0));
+    }
+
+    #[test]
+    fn rect_clamp_point() {
+        let r = Rect::from_values(10.0, 10.0, 100.0, 50.0);
+        assert_eq!(
+            r.clamp_point(Point::from_xy(0.0, 30.0)),
+            Point::from_xy(10.0, 30.0)
+        );
+        assert_eq!(
+            r.clamp_point(Point::from_xy(200.0, 200.0)),
+            r.bottom_right()
+        );
+        assert_eq!(
+            r.clamp_point(Point::from_xy(50.0, 20.0)),
+            Point::from_xy(50.0, 20.0)
+        );
+        assert_eq!(
+            r.translate(Point::from_xy(5.0, -5.0)),
+            Rect::from_values(15.0, 5.0, 100.0, 50.0)
+        );
+    }
+
+    #[test]
+    fn rect_snap_to_pixels() {
+        let r1 = Rect::from_values(0.4, 10.3, 10.3, 5.4);
+        let r2 = Rect::from_values(r1.right(), 10.3, 10.3, 5.4);
+
+        let s1 = r1.snap_to_pixels(1.0);
+        let s2 = r2.snap_to_pixels(1.0);
+        assert_eq!(s1, Rect::from_values(0.0, 10.0, 11.0, 6.0));
+        assert_eq!(s1.right(), s2.left());
+
+        // half unit steps at a scale factor of two
+        let s3 = r1.snap_to_pixels(2.0);
+        assert_eq!(s3, Rect::from_values(0.5, 10.5, 10.0, 5.0));
+    }
 }
@@ -1,4 +1,5 @@
 use crate::Scalar;
+use std::ops::{Add, Div, Mul, Sub};
 
 #[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct Size {
@@ -18,10 +19,13 @@ impl Size {
         Self::from_wh(self.width + w, self.height + h)
     }
 
-    /// Return a new Size shrunk by the specified width and height
+    /// Return a new Size shrunk by the specified width and height, stopping at zero
     ///
     pub fn shrink(&self, width: Scalar, height: Scalar) -> Self {
-        Self::from_wh(self.width - width, self.height - height)
+        Self::from_wh(
+            Scalar::max(self.width - width, 0.0),
+            Scalar::max(self.height - height, 0.0),
+        )
     }
 
     pub fn with_height(&self, h: Scalar) -> Self {
@@ -31,6 +35,42 @@ impl Size {
     pub fn with_width(&self, w: Scalar) -> Self {
         Self { width: w, ..*self }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+}
+
+impl Add for Size {
+    type Output = Size;
+
+    fn add(self, rhs: Size) -> Size {
+        self.grow(rhs.width, rhs.height)
+    }
+}
+
+impl Sub for Size {
+    type Output = Size;
+
+    fn sub(self, rhs: Size) -> Size {
+        self.shrink(rhs.width, rhs.height)
+    }
+}
+
+impl Mul<Scalar> for Size {
+    type Output = Size;
+
+    fn mul(self, rhs: Scalar) -> Size {
+        Size::from_wh(self.width * rhs, self.height * rhs)
+    }
+}
+
+impl Div<Scalar> for Size {
+    type Output = Size;
+
+    fn div(self, rhs: Scalar) -> Size {
+        Size::from_wh(self.width / rhs, self.height / rhs)
+    }
 }
 
 #[cfg(test)]
@@ -67,5 +107,20 @@ mod tests {
         let size2 = size1.shrink(1.0, 2.0);
         assert_eq!(size2.height, 18.0);
         assert_eq!(size2.width, 9.0);
+
+        let size3 = size1.shrink(15.0, 2.0);
+        assert_eq!(size3, Size::from_wh(0.0, 18.0));
+    }
+
+    #[test]
+    fn size_operators() {
+        let size1 = Size::from_wh(10.0, 20.0);
+        let size2 = Size::from_wh(1.0, 2.0);
+        assert_eq!(size1 + size2, Size::from_wh(11.0, 22.0));
+        assert_eq!(size1 - size2, Size::from_wh(9.0, 18.0));
+        assert_eq!(size1 * 2.0, Size::from_wh(20.0, 40.0));
+        assert_eq!(size1 / 2.0, Size::from_wh(5.0, 10.0));
+        assert!(!size1.is_empty());
+        assert!(size1.with_width(0.0).is_empty());
     }
 }
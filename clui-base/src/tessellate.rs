@@ -0,0 +1,925 @@
+use crate::{
+    Border, BorderDef, BoxModel, BoxRects, CluiColor, CluiVertex, Corner, CornerRadii, Padding,
+    Point, Rect, Scalar,
+};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Vertex and index data for a single window. Indices are relative to the start of `vertices`.
+///
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Geometry {
+    pub(crate) vertices: Vec<CluiVertex>,
+    pub(crate) indices: Vec<u32>,
+}
+
+impl Geometry {
+    pub(crate) fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// The smallest rect holding every vertex, or `None` if there are none
+    ///
+    pub(crate) fn bounds(&self) -> Option<Rect> {
+        let [x, y] = self.vertices.first()?.position;
+        let (min, max) = self.vertices.iter().fold(
+            (Point::from_xy(x, y), Point::from_xy(x, y)),
+            |(min, max), v| {
+                let [x, y] = v.position;
+                (
+                    Point::from_xy(min.x.min(x), min.y.min(y)),
+                    Point::from_xy(max.x.max(x), max.y.max(y)),
+                )
+            },
+        );
+        Some(Rect::from_corners(min, max))
+    }
+
+    pub(crate) fn translate(&mut self, delta: Point) {
+        for v in &mut self.vertices {
+            v.position[0] += delta.x;
+            v.position[1] += delta.y;
+        }
+    }
+
+    /// Add a quad with the corners given in clockwise order
+    ///
+    pub(crate) fn add_quad(&mut self, corners: [Point; 4], color: CluiColor) {
+        let base = self.vertices.len() as u32;
+        let color = [color.r, color.g, color.b, color.a];
+
+        self.vertices.extend(corners.iter().map(|p| CluiVertex {
+            position: [p.x, p.y],
+            color,
+            uv: [0.0, 0.0],
+        }));
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].iter().map(|i| base + i));
+    }
+
+    /// Add a rect sampling the `uv` rect of a texture
+    ///
+    pub(crate) fn add_textured_rect(&mut self, rect: &Rect, uv: &Rect, color: CluiColor) {
+        let base = self.vertices.len() as u32;
+        let color = [color.r, color.g, color.b, color.a];
+        let (p0, p1) = (rect.point, rect.bottom_right());
+        let (t0, t1) = (uv.point, uv.bottom_right());
+
+        self.vertices.extend(
+            [
+                ([p0.x, p0.y], [t0.x, t0.y]),
+                ([p1.x, p0.y], [t1.x, t0.y]),
+                ([p1.x, p1.y], [t1.x, t1.y]),
+                ([p0.x, p1.y], [t0.x, t1.y]),
+            ]
+            .iter()
+            .map(|(position, uv)| CluiVertex {
+                position: *position,
+                color,
+                uv: *uv,
+            }),
+        );
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].iter().map(|i| base + i));
+    }
+
+    pub(crate) fn add_rect(&mut self, rect: &Rect, color: CluiColor) {
+        let br = rect.bottom_right();
+        self.add_quad(
+            [
+                rect.point,
+                Point::from_xy(br.x, rect.point.y),
+                br,
+                Point::from_xy(rect.point.x, br.y),
+            ],
+            color,
+        );
+    }
+
+    /// Add a clockwise convex polygon as a triangle fan. With a `feather` width the polygon is
+    /// shrunk by half the feather and surrounded by a ring fading out to transparent.
+    ///
+    pub(crate) fn add_convex_polygon(
+        &mut self,
+        points: &[Point],
+        color: CluiColor,
+        feather: Scalar,
+    ) {
+        if points.len() < 3 {
+            return;
+        }
+        if feather <= 0.0 {
+            self.add_fan(points, color);
+            return;
+        }
+
+        let mut points = points.to_vec();
+        points.dedup();
+        let normals = contour_normals(&points, true);
+        let h = feather / 2.0;
+
+        let inner: Vec<Point> = points
+            .iter()
+            .zip(&normals)
+            .map(|(p, n)| offset(*p, *n, -h))
+            .collect();
+        self.add_fan(&inner, color);
+
+        let mut fringe: Vec<EdgePair> = points
+            .iter()
+            .zip(&normals)
+            .map(|(p, n)| (offset(*p, *n, h), offset(*p, *n, -h)))
+            .collect();
+        fringe.push(fringe[0]);
+        self.add_band(&fringe, transparent(color), color);
+    }
+
+    fn add_fan(&mut self, points: &[Point], color: CluiColor) {
+        if points.len() < 3 {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        let color = [color.r, color.g, color.b, color.a];
+
+        self.vertices.extend(points.iter().map(|p| CluiVertex {
+            position: [p.x, p.y],
+            color,
+            uv: [0.0, 0.0],
+        }));
+        for k in 1..points.len() as u32 - 1 {
+            self.indices.extend([base, base + k, base + k + 1]);
+        }
+    }
+
+    /// Add a band between two edges. Each pair holds a point on the outer edge and the matching
+    /// point on the inner edge. With a `feather` width both edges are pulled in by half the
+    /// feather and fade out to transparent over the full width.
+    ///
+    pub(crate) fn add_strip(&mut self, pairs: &[EdgePair], color: CluiColor, feather: Scalar) {
+        if pairs.len() < 2 {
+            return;
+        }
+        if feather <= 0.0 {
+            self.add_band(pairs, color, color);
+            return;
+        }
+
+        let (outer_normals, inner_normals) = band_normals(pairs);
+        let h = feather / 2.0;
+        let mut outer_fringe = Vec::with_capacity(pairs.len());
+        let mut solid = Vec::with_capacity(pairs.len());
+        let mut inner_fringe = Vec::with_capacity(pairs.len());
+
+        for ((o, i), (no, ni)) in pairs.iter().zip(outer_normals.iter().zip(&inner_normals)) {
+            outer_fringe.push((offset(*o, *no, h), offset(*o, *no, -h)));
+            solid.push((offset(*o, *no, -h), offset(*i, *ni, h)));
+            inner_fringe.push((offset(*i, *ni, h), offset(*i, *ni, -h)));
+        }
+
+        self.add_band(&outer_fringe, transparent(color), color);
+        self.add_band(&solid, color, color);
+        self.add_band(&inner_fringe, color, transparent(color));
+    }
+
+    // A band with one colour along the outer edge and another along the inner edge
+    fn add_band(&mut self, pairs: &[EdgePair], outer_color: CluiColor, inner_color: CluiColor) {
+        let base = self.vertices.len() as u32;
+        let outer_color = [outer_color.r, outer_color.g, outer_color.b, outer_color.a];
+        let inner_color = [inner_color.r, inner_color.g, inner_color.b, inner_color.a];
+
+        for (outer, inner) in pairs {
+            self.vertices.push(CluiVertex {
+                position: [outer.x, outer.y],
+                color: outer_color,
+                uv: [0.0, 0.0],
+            });
+            self.vertices.push(CluiVertex {
+                position: [inner.x, inner.y],
+                color: inner_color,
+                uv: [0.0, 0.0],
+            });
+        }
+        for k in 0..pairs.len() as u32 - 1 {
+            let o0 = base + k * 2;
+            let i0 = o0 + 1;
+            let o1 = o0 + 2;
+            let i1 = o0 + 3;
+            self.indices.extend([o0, o1, i1, o0, i1, i0]);
+        }
+    }
+}
+
+/// Options controlling how windows are turned into triangles
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TessellationOptions {
+    /// Surround every filled shape and border with a ring of vertices fading to transparent,
+    /// giving smooth edges without MSAA
+    pub anti_alias: bool,
+    /// Width of the anti-aliasing ring in logical units, so it grows with the scale factor
+    pub feather_width: Scalar,
+    /// Round box edges to the physical pixel grid so thin borders stay sharp
+    pub snap_to_pixels: bool,
+}
+
+impl Default for TessellationOptions {
+    fn default() -> Self {
+        Self {
+            anti_alias: false,
+            feather_width: 1.0,
+            snap_to_pixels: false,
+        }
+    }
+}
+
+impl TessellationOptions {
+    // The feather width in layout units, for a canvas with `canvas_scale` logical pixels per unit
+    pub(crate) fn feather(&self, canvas_scale: Scalar) -> Scalar {
+        if self.anti_alias {
+            self.feather_width / canvas_scale
+        } else {
+            0.0
+        }
+    }
+}
+
+fn transparent(color: CluiColor) -> CluiColor {
+    CluiColor { a: 0.0, ..color }
+}
+
+fn offset(p: Point, normal: Point, distance: Scalar) -> Point {
+    p + normal * distance
+}
+
+// Outward unit normal of the edge from a to b on a clockwise contour
+fn edge_normal(a: Point, b: Point) -> Option<Point> {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length <= 1e-6 {
+        None
+    } else {
+        Some(Point::from_xy(dy / length, -dx / length))
+    }
+}
+
+// Combine the normals of two edges meeting at a vertex into an offset that moves both edges
+// out by one unit
+fn miter(n0: Point, n1: Point) -> Point {
+    let m = Point::from_xy((n0.x + n1.x) / 2.0, (n0.y + n1.y) / 2.0);
+    let d = m.x * m.x + m.y * m.y;
+    if d <= 1e-6 {
+        return n0;
+    }
+    // limit the spike on very sharp angles
+    let scale = Scalar::min(1.0 / d, 16.0);
+    Point::from_xy(m.x * scale, m.y * scale)
+}
+
+// Per vertex offsets for a clockwise polyline, skipping over repeated points. Ends of an open
+// polyline only have one edge to go by.
+fn contour_normals(points: &[Point], closed: bool) -> Vec<Point> {
+    let n = points.len();
+    let find_edge = |k: usize, forward: bool| {
+        for step in 1..n {
+            let j = if forward {
+                if !closed && k + step >= n {
+                    return None;
+                }
+                (k + step) % n
+            } else {
+                if !closed && step > k {
+                    return None;
+                }
+                (k + n - step) % n
+            };
+            let edge = if forward {
+                edge_normal(points[k], points[j])
+            } else {
+                edge_normal(points[j], points[k])
+            };
+            if edge.is_some() {
+                return edge;
+            }
+        }
+        None
+    };
+
+    (0..n)
+        .map(|k| match (find_edge(k, false), find_edge(k, true)) {
+            (Some(n0), Some(n1)) => miter(n0, n1),
+            (Some(n), None) | (None, Some(n)) => n,
+            (None, None) => Point::default(),
+        })
+        .collect()
+}
+
+// Outward offsets for the outer and inner edges of a band. At the two ends the offset runs
+// along the line joining the pair, so mitred joins between neighbouring bands stay closed.
+fn band_normals(pairs: &[EdgePair]) -> (Vec<Point>, Vec<Point>) {
+    let outer: Vec<Point> = pairs.iter().map(|(o, _)| *o).collect();
+    let inner: Vec<Point> = pairs.iter().map(|(_, i)| *i).collect();
+    let mut outer_normals = contour_normals(&outer, false);
+    let mut inner_normals = contour_normals(&inner, false);
+
+    for k in 0..pairs.len() {
+        if inner_normals[k] == Point::default() {
+            inner_normals[k] = outer_normals[k];
+        }
+    }
+
+    for k in [0, pairs.len() - 1] {
+        let (o, i) = pairs[k];
+        let across = (o.x - i.x, o.y - i.y);
+        let length = (across.0 * across.0 + across.1 * across.1).sqrt();
+        if length <= 1e-6 {
+            continue;
+        }
+        let d = Point::from_xy(across.0 / length, across.1 / length);
+        for normals in [&mut outer_normals, &mut inner_normals] {
+            let n = normals[k];
+            let cos = d.x * n.x + d.y * n.y;
+            if cos > 0.1 {
+                normals[k] = Point::from_xy(d.x / cos, d.y / cos);
+            }
+        }
+    }
+
+    (outer_normals, inner_normals)
+}
+
+pub(crate) type EdgePair = (Point, Point);
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+// Corners in the order they are visited walking clockwise around a box
+const CLOCKWISE: [Corner; 4] = [
+    Corner::TopLeft,
+    Corner::TopRight,
+    Corner::BottomRight,
+    Corner::BottomLeft,
+];
+
+/// Background and borders for a resolved box, whose border `widths` are in pixels. `scale` is the number of physical pixels per
+/// unit, used to pick how finely curves are tessellated, and `canvas_scale` the number of
+/// logical pixels per unit, which the feather width is given in.
+///
+pub(crate) fn add_box(
+    geometry: &mut Geometry,
+    model: &BoxModel,
+    widths: &Padding,
+    rects: &BoxRects,
+    options: &TessellationOptions,
+    scale: Scalar,
+    canvas_scale: Scalar,
+) {
+    let feather = options.feather(canvas_scale);
+    let snapped;
+    let rects = if options.snap_to_pixels {
+        snapped = rects.snap_to_pixels(scale);
+        &snapped
+    } else {
+        rects
+    };
+
+    let outer_radii = model.border_radius.clamped_to(rects.border.size);
+    let segments = CLOCKWISE.map(|corner| segment_count(outer_radii[corner], scale));
+
+    let outer = corner_arcs(
+        &rects.border,
+        CLOCKWISE.map(|corner| (outer_radii[corner], outer_radii[corner])),
+        segments,
+    );
+
+    // layout only containers have a transparent background, and draw nothing for it
+    if model.background_color.a > 0.0 {
+        let background: Vec<Point> = outer.iter().flatten().copied().collect();
+        geometry.add_convex_polygon(&background, model.background_color, feather);
+    }
+
+    // the inner edge of the border follows the outer curve, so its radii shrink by the width
+    // of the sides meeting at each corner
+    let corner_widths = |corner| match corner {
+        Corner::TopLeft => (widths.left, widths.top),
+        Corner::TopRight => (widths.right, widths.top),
+        Corner::BottomRight => (widths.right, widths.bottom),
+        Corner::BottomLeft => (widths.left, widths.bottom),
+    };
+    let inner = corner_arcs(
+        &rects.padding,
+        CLOCKWISE.map(|corner| {
+            let (wx, wy) = corner_widths(corner);
+            (
+                Scalar::max(outer_radii[corner] - wx, 0.0),
+                Scalar::max(outer_radii[corner] - wy, 0.0),
+            )
+        }),
+        segments,
+    );
+
+    add_borders(
+        geometry,
+        &model.border,
+        widths,
+        &outer,
+        &inner,
+        feather,
+        scale,
+    );
+}
+
+/// Number of segments for a quarter circle, keeping the curve within a quarter of a pixel of
+/// the true arc. Always even so the arc has a vertex at its midpoint.
+///
+fn segment_count(radius: Scalar, scale: Scalar) -> usize {
+    if radius <= 0.0 {
+        return 0;
+    }
+    let tolerance = 0.25;
+    let pixels = radius * scale;
+    let step = if pixels <= tolerance {
+        FRAC_PI_2
+    } else {
+        2.0 * (1.0 - tolerance / pixels).acos()
+    };
+    let n = ((FRAC_PI_2 / step).ceil() as usize).clamp(2, 64);
+    n + n % 2
+}
+
+// Points along each corner arc of a rect, clockwise, with `segments + 1` points per corner.
+// A corner with no segments is a single sharp point.
+fn corner_arcs(rect: &Rect, radii: [(Scalar, Scalar); 4], segments: [usize; 4]) -> [Vec<Point>; 4] {
+    let mut arcs: [Vec<Point>; 4] = Default::default();
+    for (k, corner) in CLOCKWISE.iter().enumerate() {
+        let (rx, ry) = radii[k];
+        let (cx, cy, start) = match corner {
+            Corner::TopLeft => (rect.left() + rx, rect.top() + ry, PI),
+            Corner::TopRight => (rect.right() - rx, rect.top() + ry, PI * 1.5),
+            Corner::BottomRight => (rect.right() - rx, rect.bottom() - ry, 0.0),
+            Corner::BottomLeft => (rect.left() + rx, rect.bottom() - ry, FRAC_PI_2),
+        };
+        let n = segments[k];
+        arcs[k] = if n == 0 {
+            vec![Point::from_xy(cx, cy)]
+        } else {
+            (0..=n)
+                .map(|i| {
+                    let angle = start + FRAC_PI_2 * i as Scalar / n as Scalar;
+                    Point::from_xy(cx + angle.cos() * rx, cy + angle.sin() * ry)
+                })
+                .collect()
+        };
+    }
+    arcs
+}
+
+// Each side runs clockwise from the middle of one corner to the middle of the next. Joining
+// the outer edge to the inner edge there mitres the joins, so sides of different widths or
+// colours meet on the diagonal.
+fn add_borders(
+    geometry: &mut Geometry,
+    border: &Border,
+    widths: &Padding,
+    outer: &[Vec<Point>; 4],
+    inner: &[Vec<Point>; 4],
+    feather: Scalar,
+    scale: Scalar,
+) {
+    let sides = [
+        (Side::Top, &border.top, widths.top),
+        (Side::Right, &border.right, widths.right),
+        (Side::Bottom, &border.bottom, widths.bottom),
+        (Side::Left, &border.left, widths.left),
+    ];
+
+    for (a, (side, def, width)) in sides.iter().enumerate() {
+        let b = (a + 1) % 4;
+        let from = outer[a].len() / 2;
+        let to = outer[b].len() / 2;
+
+        let pairs: Vec<EdgePair> = outer[a][from..]
+            .iter()
+            .zip(&inner[a][from..])
+            .chain(outer[b][..=to].iter().zip(&inner[b][..=to]))
+            .map(|(o, i)| (*o, *i))
+            .collect();
+        add_border_side(geometry, *side, def, *width, &pairs, feather, scale);
+    }
+}
+
+fn add_border_side(
+    geometry: &mut Geometry,
+    side: Side,
+    def: &BorderDef,
+    width: Scalar,
+    pairs: &[EdgePair],
+    feather: Scalar,
+    scale: Scalar,
+) {
+    let color = match def.metrics() {
+        Some(metrics) if width > 0.0 => metrics.color,
+        _ => return,
+    };
+    let top_left = matches!(side, Side::Top | Side::Left);
+
+    match def {
+        BorderDef::None => {}
+        BorderDef::Solid(_) => geometry.add_strip(pairs, color, feather),
+        BorderDef::Dashed(_) => {
+            add_dashes(geometry, pairs, width * 3.0, width * 2.0, color, feather)
+        }
+        BorderDef::Dotted(_) => add_dashes(geometry, pairs, width, width, color, feather),
+        BorderDef::Double(_) => {
+            // too few pixels wide to leave a visible gap
+            if width * scale < 3.0 {
+                geometry.add_strip(pairs, color, feather);
+            } else {
+                geometry.add_strip(&sub_band(pairs, 0.0, 1.0 / 3.0), color, feather);
+                geometry.add_strip(&sub_band(pairs, 2.0 / 3.0, 1.0), color, feather);
+            }
+        }
+        BorderDef::Inset(_) => geometry.add_strip(pairs, shade(color, !top_left), feather),
+        BorderDef::Outset(_) => geometry.add_strip(pairs, shade(color, top_left), feather),
+    }
+}
+
+fn shade(color: CluiColor, lighten: bool) -> CluiColor {
+    let f = |c: f32| {
+        if lighten {
+            c + (1.0 - c) * 0.5
+        } else {
+            c * 0.5
+        }
+    };
+    CluiColor {
+        r: f(color.r),
+        g: f(color.g),
+        b: f(color.b),
+        a: color.a,
+    }
+}
+
+// The part of a band between fractions t0 and t1 of the way from its outer to its inner edge
+fn sub_band(pairs: &[EdgePair], t0: Scalar, t1: Scalar) -> Vec<EdgePair> {
+    pairs
+        .iter()
+        .map(|(o, i)| (o.lerp(*i, t0), o.lerp(*i, t1)))
+        .collect()
+}
+
+// Split a band into dashes along the length of its centre line. The pattern is stretched so
+// the band starts and ends with a full dash.
+fn add_dashes(
+    geometry: &mut Geometry,
+    pairs: &[EdgePair],
+    dash: Scalar,
+    gap: Scalar,
+    color: CluiColor,
+    feather: Scalar,
+) {
+    let mut distances = Vec::with_capacity(pairs.len());
+    let mut total = 0.0;
+    let mut last = pairs[0].0.lerp(pairs[0].1, 0.5);
+    for (o, i) in pairs {
+        let mid = o.lerp(*i, 0.5);
+        total += (mid - last).length();
+        distances.push(total);
+        last = mid;
+    }
+    if total <= 0.0 {
+        return;
+    }
+
+    let count = Scalar::max(((total + gap) / (dash + gap)).floor(), 1.0);
+    let scale = (total + gap) / (count * (dash + gap));
+    let (dash, gap) = (dash * scale, gap * scale);
+
+    for n in 0..count as usize {
+        let start = n as Scalar * (dash + gap);
+        geometry.add_strip(
+            &slice_band(pairs, &distances, start, Scalar::min(start + dash, total)),
+            color,
+            feather,
+        );
+    }
+}
+
+// The part of a band between two distances along its length
+fn slice_band(
+    pairs: &[EdgePair],
+    distances: &[Scalar],
+    start: Scalar,
+    end: Scalar,
+) -> Vec<EdgePair> {
+    let pair_at = |d: Scalar| {
+        let k = distances
+            .iter()
+            .position(|x| *x >= d)
+            .unwrap_or(distances.len() - 1)
+            .max(1);
+        let span = distances[k] - distances[k - 1];
+        let t = if span > 0.0 {
+            (d - distances[k - 1]) / span
+        } else {
+            0.0
+        };
+        (
+            pairs[k - 1].0.lerp(pairs[k].0, t),
+            pairs[k - 1].1.lerp(pairs[k].1, t),
+        )
+    };
+
+    let mut slice = vec![pair_at(start)];
+    slice.extend(
+        pairs
+            .iter()
+            .zip(distances)
+            .filter(|(_, d)| **d > start && **d < end)
+            .map(|(p, _)| *p),
+    );
+    slice.push(pair_at(end));
+    slice
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tessellate::{add_box, segment_count, Geometry, TessellationOptions};
+    use crate::{
+        Border, BorderDef, BorderMetrics, BoxModel, CluiColor, CornerRadii, LengthContext, Padding,
+        Rect,
+    };
+
+    fn red() -> CluiColor {
+        CluiColor {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0,
+            a: 1.0,
+        }
+    }
+
+    fn tessellate_with(model: BoxModel, options: TessellationOptions) -> Geometry {
+        let context = LengthContext::default();
+        let rects = model.resolve(&Rect::from_values(0.0, 0.0, 100.0, 50.0), &context);
+        let widths = model.border.widths().resolve(&context);
+        let mut geometry = Geometry::default();
+        add_box(&mut geometry, &model, &widths, &rects, &options, 1.0, 1.0);
+        geometry
+    }
+
+    fn tessellate_model(model: BoxModel) -> Geometry {
+        tessellate_with(model, TessellationOptions::default())
+    }
+
+    fn tessellate(border: Border) -> Geometry {
+        tessellate_model(BoxModel {
+            border,
+            ..Default::default()
+        })
+    }
+
+    fn metrics(size: f32) -> BorderMetrics {
+        BorderMetrics {
+            size: size.into(),
+            color: red(),
+        }
+    }
+
+    #[test]
+    fn solid_border_mitred_corners() {
+        let geometry = tessellate(Border {
+            left: BorderDef::Solid(metrics(2.0)),
+            top: BorderDef::Solid(metrics(6.0)),
+            ..Default::default()
+        });
+
+        // two sides, and nothing for the transparent background
+        assert_eq!(geometry.vertices.len(), 4 + 4);
+        assert_eq!(geometry.indices.len(), 6 * 2);
+        assert!(tessellate_model(BoxModel::default()).vertices.is_empty());
+
+        // both sides meet at the outer corner and the inner corner of the mitre
+        let top = &geometry.vertices[0..4];
+        let left = &geometry.vertices[4..8];
+        for position in [[0.0, 0.0], [2.0, 6.0]] {
+            assert!(top.iter().any(|v| v.position == position));
+            assert!(left.iter().any(|v| v.position == position));
+        }
+    }
+
+    #[test]
+    fn styled_borders() {
+        let dashed = tessellate(Border::all(BorderDef::Dashed(metrics(2.0))));
+        let solid = tessellate(Border::all(BorderDef::Solid(metrics(2.0))));
+        let double = tessellate(Border::all(BorderDef::Double(metrics(6.0))));
+        let dotted = tessellate(Border::all(BorderDef::Dotted(metrics(2.0))));
+
+        assert_eq!(solid.vertices.len(), 4 * 4);
+        assert_eq!(double.vertices.len(), 8 * 4);
+
+        // the gap is only left when the border is three pixels wide on screen
+        let model = BoxModel {
+            border: Border::all(BorderDef::Double(metrics(2.0))),
+            ..Default::default()
+        };
+        let context = LengthContext::default();
+        let rects = model.resolve(&Rect::from_values(0.0, 0.0, 100.0, 50.0), &context);
+        let widths = model.border.widths().resolve(&context);
+        let options = TessellationOptions::default();
+        let mut thin = Geometry::default();
+        add_box(&mut thin, &model, &widths, &rects, &options, 1.0, 1.0);
+        let mut scaled = Geometry::default();
+        add_box(&mut scaled, &model, &widths, &rects, &options, 2.0, 2.0);
+        assert_eq!(thin.vertices.len(), 4 * 4);
+        assert_eq!(scaled.vertices.len(), 8 * 4);
+        assert!(dashed.vertices.len() > solid.vertices.len());
+        assert!(dotted.vertices.len() > dashed.vertices.len());
+    }
+
+    #[test]
+    fn inset_and_outset_shading() {
+        let inset = tessellate(Border::all(BorderDef::Inset(metrics(2.0))));
+        let outset = tessellate(Border::all(BorderDef::Outset(metrics(2.0))));
+
+        // top side comes first
+        assert_eq!(inset.vertices[0].color, [0.5, 0.0, 0.0, 1.0]);
+        assert_eq!(outset.vertices[0].color, [1.0, 0.5, 0.5, 1.0]);
+        // then right
+        assert_eq!(inset.vertices[4].color, [1.0, 0.5, 0.5, 1.0]);
+        assert_eq!(outset.vertices[4].color, [0.5, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn segments_adapt_to_scale() {
+        assert_eq!(segment_count(0.0, 1.0), 0);
+        assert!(segment_count(2.0, 1.0) >= 2);
+        assert!(segment_count(10.0, 2.0) > segment_count(10.0, 1.0));
+        assert!(segment_count(50.0, 1.0) > segment_count(5.0, 1.0));
+        assert_eq!(segment_count(7.0, 1.5) % 2, 0);
+    }
+
+    #[test]
+    fn rounded_background() {
+        let geometry = tessellate_model(BoxModel {
+            border_radius: CornerRadii {
+                top_left: 10.0,
+                ..Default::default()
+            },
+            background_color: red(),
+            ..Default::default()
+        });
+
+        let n = segment_count(10.0, 1.0);
+        assert_eq!(geometry.vertices.len(), n + 1 + 3);
+        for v in &geometry.vertices {
+            let [x, y] = v.position;
+            assert!((0.0..=100.0).contains(&x) && (0.0..=50.0).contains(&y));
+            // nothing inside the cut off corner
+            let (dx, dy) = (10.0 - x, 10.0 - y);
+            assert!(x >= 10.0 || y >= 10.0 || dx * dx + dy * dy <= 100.01);
+        }
+    }
+
+    #[test]
+    fn rounded_border_follows_curve() {
+        let geometry = tessellate_model(BoxModel {
+            border: Border::all(BorderDef::Solid(metrics(4.0))),
+            border_radius: CornerRadii::all(10.0),
+            ..Default::default()
+        });
+
+        let n = segment_count(10.0, 1.0);
+        // every side covers half of each of its corners, as outer/inner pairs
+        assert_eq!(geometry.vertices.len(), 4 * 2 * (n + 2));
+
+        // the inner edge of the top left corner is a radius 6 arc around the same centre
+        let top = &geometry.vertices[..2 * (n + 2)];
+        for pair in top.chunks(2).take(n / 2 + 1) {
+            let [ox, oy] = pair[0].position;
+            let [ix, iy] = pair[1].position;
+            let outer = ((10.0 - ox).powi(2) + (10.0 - oy).powi(2)).sqrt();
+            let inner = ((10.0 - ix).powi(2) + (10.0 - iy).powi(2)).sqrt();
+            assert!((outer - 10.0).abs() < 0.001);
+            assert!((inner - 6.0).abs() < 0.001);
+        }
+    }
+
+    fn anti_aliased() -> TessellationOptions {
+        TessellationOptions {
+            anti_alias: true,
+            feather_width: 2.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn feathered_background() {
+        let geometry = tessellate_with(
+            BoxModel {
+                background_color: red(),
+                ..Default::default()
+            },
+            anti_aliased(),
+        );
+
+        // shrunk fill plus a closed ring of four quads
+        assert_eq!(geometry.vertices.len(), 4 + 5 * 2);
+        assert_eq!(geometry.vertices[0].position, [1.0, 1.0]);
+        assert_eq!(geometry.vertices[2].position, [99.0, 49.0]);
+
+        let outer: Vec<_> = geometry.vertices[4..].iter().step_by(2).collect();
+        assert_eq!(outer[0].position, [-1.0, -1.0]);
+        assert_eq!(outer[2].position, [101.0, 51.0]);
+        assert!(outer.iter().all(|v| v.color[3] == 0.0));
+        assert!(geometry.vertices[5..]
+            .iter()
+            .step_by(2)
+            .all(|v| v.color == [1.0, 0.0, 0.0, 1.0]));
+
+        // the width is in logical pixels, so a canvas scaled up by two feathers half as far
+        let model = BoxModel {
+            background_color: red(),
+            ..Default::default()
+        };
+        let context = LengthContext::default();
+        let rects = model.resolve(&Rect::from_values(0.0, 0.0, 100.0, 50.0), &context);
+        let widths = model.border.widths().resolve(&context);
+        let mut geometry = Geometry::default();
+        add_box(
+            &mut geometry,
+            &model,
+            &widths,
+            &rects,
+            &anti_aliased(),
+            2.0,
+            2.0,
+        );
+        assert_eq!(geometry.vertices[0].position, [0.5, 0.5]);
+        assert_eq!(geometry.vertices[4].position, [-0.5, -0.5]);
+    }
+
+    #[test]
+    fn feathered_border_stays_mitred() {
+        let geometry = tessellate_with(
+            BoxModel {
+                border: Border {
+                    top: BorderDef::Solid(metrics(4.0)),
+                    left: BorderDef::Solid(metrics(4.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            anti_aliased(),
+        );
+
+        let borders = &geometry.vertices;
+        let transparent_at = |x: f32, y: f32| {
+            borders
+                .iter()
+                .filter(|v| {
+                    (v.position[0] - x).abs() < 1e-4
+                        && (v.position[1] - y).abs() < 1e-4
+                        && v.color[3] == 0.0
+                })
+                .count()
+        };
+        // the outer fringes of both sides meet on the diagonal outside the corner
+        assert_eq!(transparent_at(-1.0, -1.0), 2);
+        // and so do the inner fringes inside the mitre
+        assert_eq!(transparent_at(5.0, 5.0), 2);
+    }
+
+    #[test]
+    fn snapped_borders() {
+        let model = BoxModel {
+            border: Border::all(BorderDef::Solid(metrics(1.0))),
+            background_color: red(),
+            ..Default::default()
+        };
+        let context = LengthContext::default();
+        let rects = model.resolve(&Rect::from_values(10.3, 20.6, 50.0, 20.0), &context);
+        let widths = model.border.widths().resolve(&context);
+        let options = TessellationOptions {
+            snap_to_pixels: true,
+            ..Default::default()
+        };
+        let mut geometry = Geometry::default();
+        add_box(&mut geometry, &model, &widths, &rects, &options, 1.0, 1.0);
+
+        assert!(geometry
+            .vertices
+            .iter()
+            .all(|v| v.position[0].fract() == 0.0 && v.position[1].fract() == 0.0));
+        assert_eq!(geometry.vertices[0].position, [10.0, 21.0]);
+        assert_eq!(geometry.vertices[2].position, [60.0, 41.0]);
+    }
+}
@@ -0,0 +1,1555 @@
+use crate::atlas::AtlasFormat;
+use crate::font::{Font, FontCollectionKey, FontKey, FontMetrics, FontStyle, Fonts};
+use crate::markup;
+use crate::tessellate::Geometry;
+use crate::{CluiColor, CluiError, Point, Rect, Scalar, Size};
+use rustybuzz::{Direction, UnicodeBuffer};
+use std::ops::Range;
+use unicode_bidi::{BidiInfo, Level};
+use unicode_script::{Script, UnicodeScript};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How lines are placed across the width of the text box
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum TextAlign {
+    /// Left in left to right paragraphs, right in right to left ones
+    #[default]
+    Start,
+    End,
+    Left,
+    Center,
+    Right,
+    /// Lines that were wrapped are stretched to the full width by widening their spaces. The
+    /// last line of each paragraph is aligned to the start.
+    Justify,
+}
+
+/// The base direction of each paragraph
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum TextDirection {
+    /// Taken from the first strong character of the paragraph, left to right if it has none
+    #[default]
+    Auto,
+    LeftToRight,
+    RightToLeft,
+}
+
+/// What happens to text that does not fit in the width or the line limit
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum TextOverflow {
+    /// The text is laid out in full and runs past the box
+    #[default]
+    Clip,
+    /// The last visible line is cut short and ends in an ellipsis
+    Ellipsis,
+}
+
+/// The distance between baselines
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum LineHeight {
+    /// The line height the font asks for
+    #[default]
+    Normal,
+    /// A multiple of the font size
+    Relative(Scalar),
+    /// A fixed height in layout units
+    Fixed(Scalar),
+}
+
+impl LineHeight {
+    pub fn resolve(&self, metrics: &FontMetrics, font_size: Scalar) -> Scalar {
+        match *self {
+            LineHeight::Normal => metrics.line_height(),
+            LineHeight::Relative(factor) => factor * font_size,
+            LineHeight::Fixed(height) => height,
+        }
+    }
+}
+
+/// How the characters of a text are drawn. Sizes are in layout units.
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TextStyle {
+    /// The font used when no collection is set
+    pub font: FontKey,
+    /// Lay out with the fonts of this collection for `font_style`, falling back through them
+    /// for characters the first font does not have
+    pub collection: Option<FontCollectionKey>,
+    pub font_style: FontStyle,
+    pub size: Scalar,
+    pub color: CluiColor,
+    /// Extra space after each grapheme
+    pub letter_spacing: Scalar,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font: FontKey::default(),
+            collection: None,
+            font_style: FontStyle::default(),
+            size: 16.0,
+            color: CluiColor {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            letter_spacing: 0.0,
+        }
+    }
+}
+
+/// How a text is broken into lines and placed in its box
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ParagraphStyle {
+    pub align: TextAlign,
+    pub line_height: LineHeight,
+    /// Break lines at word boundaries to fit the width. Words wider than a line are broken
+    /// between graphemes. Line feeds always break.
+    pub wrap: bool,
+    pub overflow: TextOverflow,
+    /// Lines past this are dropped
+    pub max_lines: Option<usize>,
+    pub direction: TextDirection,
+}
+
+impl Default for ParagraphStyle {
+    fn default() -> Self {
+        Self {
+            align: TextAlign::default(),
+            line_height: LineHeight::default(),
+            wrap: true,
+            overflow: TextOverflow::default(),
+            max_lines: None,
+            direction: TextDirection::default(),
+        }
+    }
+}
+
+/// An image drawn in the flow of a text, as if it were a character
+///
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct InlineImage {
+    /// The name the image was added to `Fonts` with
+    pub name: String,
+    /// Size in layout units. `None` is as tall as the span's font size, keeping the image's
+    /// aspect ratio.
+    pub size: Option<Size>,
+}
+
+/// A range of the text drawn in its own style
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct TextSpan {
+    /// Bytes of the text the span covers
+    pub range: Range<usize>,
+    pub style: TextStyle,
+    /// Draw this image for each character of the span instead of its glyphs. The characters
+    /// are usually a single `CluiText::IMAGE_PLACEHOLDER`.
+    pub image: Option<InlineImage>,
+}
+
+/// A string with its style, as drawn in a window's content box
+///
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct CluiText {
+    pub text: String,
+    /// The style of text not covered by a span. Its font also sets the minimum line height
+    /// and draws the ellipsis.
+    pub style: TextStyle,
+    /// Ranges drawn in other styles. Spans should not overlap, where they do the one starting
+    /// first wins.
+    pub spans: Vec<TextSpan>,
+    pub paragraph: ParagraphStyle,
+}
+
+/// One glyph placed by text layout. Positions are the pen position on the baseline, relative
+/// to the top left of the text box.
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PositionedGlyph {
+    pub font: FontKey,
+    pub glyph: u16,
+    pub size: Scalar,
+    pub color: CluiColor,
+    pub position: Point,
+    pub advance: Scalar,
+    /// Byte offset in the text of the first character this glyph was shaped from. Glyphs
+    /// of one cluster, such as a base and its marks, share it.
+    pub cluster: usize,
+    /// The glyph is part of a right to left run
+    pub rtl: bool,
+}
+
+/// An inline image placed by text layout
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct PositionedImage {
+    pub name: String,
+    /// Relative to the top left of the text box, with the bottom on the baseline
+    pub rect: Rect,
+    /// Only the alpha is used, the image keeps its own colors
+    pub color: CluiColor,
+    /// Byte offset in the text of the image's character
+    pub cluster: usize,
+}
+
+/// A line of laid out text. Its glyphs are in display order, left to right.
+///
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct TextLine {
+    /// Bytes of the text on this line, including trailing spaces and the line feed
+    pub text_range: Range<usize>,
+    pub glyph_range: Range<usize>,
+    pub top: Scalar,
+    pub height: Scalar,
+    pub baseline: Scalar,
+    /// Start of the line after alignment
+    pub left: Scalar,
+    /// Width without trailing spaces
+    pub width: Scalar,
+    /// The line belongs to a right to left paragraph
+    pub rtl: bool,
+    /// Every caret position on the line in logical order, as a byte offset at a grapheme
+    /// boundary and the x it is drawn at
+    pub carets: Vec<(usize, Scalar)>,
+}
+
+/// The result of laying out a `CluiText`
+///
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub images: Vec<PositionedImage>,
+    pub lines: Vec<TextLine>,
+    /// Width of the widest line by the total line height
+    pub size: Size,
+    /// Lines were dropped or cut short to fit
+    pub truncated: bool,
+}
+
+impl TextLayout {
+    // Every caret stop as (line, stop index), in logical order
+    fn stops(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(l, line)| (0..line.carets.len()).map(move |c| (l, c)))
+    }
+
+    // The stop for a byte offset. Offsets at the end of one line and the start of the next
+    // resolve to the next line.
+    fn find_stop(&self, index: usize) -> Option<(usize, usize)> {
+        self.stops()
+            .filter(|(l, c)| self.lines[*l].carets[*c].0 == index)
+            .last()
+    }
+
+    /// Where to draw the caret for a byte offset in the text, as a zero width rect covering
+    /// the line. Returns `None` for offsets that are not at a grapheme boundary or were
+    /// truncated away.
+    ///
+    pub fn caret_rect(&self, index: usize) -> Option<Rect> {
+        let (l, c) = self.find_stop(index)?;
+        let line = &self.lines[l];
+        Some(Rect::from_pos_and_size(
+            Point::from_xy(line.carets[c].1, line.top),
+            Size::from_wh(0.0, line.height),
+        ))
+    }
+
+    /// The offset the caret at `index` moves to on the left arrow key. In right to left text
+    /// this moves forward through the text. Returns `index` if the caret cannot move.
+    ///
+    pub fn caret_left(&self, index: usize) -> usize {
+        self.move_caret(index, false)
+    }
+
+    /// The offset the caret at `index` moves to on the right arrow key
+    ///
+    pub fn caret_right(&self, index: usize) -> usize {
+        self.move_caret(index, true)
+    }
+
+    fn move_caret(&self, index: usize, right: bool) -> usize {
+        let (l, c) = match self.find_stop(index) {
+            Some(stop) => stop,
+            None => return index,
+        };
+        let line = &self.lines[l];
+        let x = line.carets[c].1;
+        // the nearest stop in that direction, skipping ones drawn at the same x
+        let next = line
+            .carets
+            .iter()
+            .filter(|(i, cx)| *i != index && if right { *cx > x } else { *cx < x })
+            .min_by(|a, b| (a.1 - x).abs().total_cmp(&(b.1 - x).abs()));
+        if let Some((i, _)) = next {
+            return *i;
+        }
+        // off the edge of the line, to the adjacent line in reading order
+        let forward = right != line.rtl;
+        let adjacent = if forward {
+            self.lines.get(l + 1).and_then(|line| line.carets.first())
+        } else {
+            l.checked_sub(1)
+                .and_then(|l| self.lines[l].carets.iter().rev().find(|(i, _)| *i != index))
+        };
+        adjacent.map_or(index, |(i, _)| *i)
+    }
+
+    /// The byte offset of the caret stop nearest to a point
+    ///
+    pub fn hit_test(&self, point: Point) -> usize {
+        let line = self
+            .lines
+            .iter()
+            .find(|line| point.y < line.top + line.height)
+            .or(self.lines.last());
+        line.and_then(|line| {
+            line.carets
+                .iter()
+                .min_by(|a, b| (a.1 - point.x).abs().total_cmp(&(b.1 - point.x).abs()))
+        })
+        .map_or(0, |(i, _)| *i)
+    }
+}
+
+// A glyph before it is placed on a line
+#[derive(Copy, Clone, Debug)]
+struct ShapedGlyph {
+    font: FontKey,
+    glyph: u16,
+    cluster: usize,
+    advance: Scalar,
+    // from the pen position, y down
+    offset: Point,
+    whitespace: bool,
+    // bidi embedding level, odd for right to left
+    level: u8,
+    // index into the resolved styles, 0 for the text's own style and the span index + 1 for
+    // spans
+    style: usize,
+}
+
+// A style with its fonts looked up for layout
+struct ResolvedStyle<'a> {
+    style: TextStyle,
+    chain: Vec<(FontKey, &'a Font)>,
+    // the name and size of the image drawn for each character
+    image: Option<(&'a str, Size)>,
+}
+
+impl ResolvedStyle<'_> {
+    // How far the style's glyphs reach above and below the baseline, each with half the
+    // leading added, as CSS inline boxes do
+    fn extents(&self, line_height: &LineHeight) -> (Scalar, Scalar) {
+        if let Some((_, size)) = self.image {
+            return (size.height, 0.0);
+        }
+        let font = match self.chain.first() {
+            Some((_, font)) => font,
+            None => return (0.0, 0.0),
+        };
+        let metrics = font.metrics(self.style.size);
+        let leading =
+            line_height.resolve(&metrics, self.style.size) - metrics.ascent - metrics.descent;
+        (
+            metrics.ascent + leading / 2.0,
+            metrics.descent + leading / 2.0,
+        )
+    }
+}
+
+// Glyph and text ranges of a line before it is placed
+#[derive(Clone, Debug)]
+struct LineBreak {
+    glyphs: Range<usize>,
+    text: Range<usize>,
+    width: Scalar,
+    // ended by wrapping rather than a line feed or the end of the text
+    wrapped: bool,
+    // level of the paragraph
+    level: u8,
+}
+
+impl CluiText {
+    /// The character inline images take the place of, U+FFFC OBJECT REPLACEMENT CHARACTER
+    ///
+    pub const IMAGE_PLACEHOLDER: char = '\u{fffc}';
+
+    pub fn new(text: impl Into<String>, style: TextStyle) -> Self {
+        Self {
+            text: text.into(),
+            style,
+            spans: Vec::new(),
+            paragraph: ParagraphStyle::default(),
+        }
+    }
+
+    /// Parse markup such as `Deals <color=red>50</color> fire damage <icon=fire/>` into text
+    /// and spans. `style` is the style outside of any tag, and fonts and collections are
+    /// looked up by name in `fonts`.
+    ///
+    /// Tags are `<b>`, `<i>`, `<color=red>` or `<color=#ff000080>`, `<size=24>`,
+    /// `<font=name>` and the self closing `<icon=name/>`. Tags nest and are closed by name, as
+    /// in `</color>`. `&lt;`, `&gt;` and `&amp;` escape the characters markup uses. `<b>` and
+    /// `<i>` pick fonts from a collection, so they are an error unless `style` or an enclosing
+    /// `<font>` tag sets one.
+    ///
+    pub fn from_markup(markup: &str, style: TextStyle, fonts: &Fonts) -> Result<Self, CluiError> {
+        markup::parse(markup, style, fonts)
+    }
+
+    /// Append text drawn in `style`
+    ///
+    pub fn push(&mut self, text: &str, style: TextStyle) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.spans.push(TextSpan {
+            range: start..self.text.len(),
+            style,
+            image: None,
+        });
+    }
+
+    /// Append an image, drawn with the opacity of `style` and sized by its font size unless
+    /// the image has a size
+    ///
+    pub fn push_image(&mut self, image: InlineImage, style: TextStyle) {
+        let start = self.text.len();
+        self.text.push(Self::IMAGE_PLACEHOLDER);
+        self.spans.push(TextSpan {
+            range: start..self.text.len(),
+            style,
+            image: Some(image),
+        });
+    }
+
+    // The text's own style, then one per span
+    fn resolve_styles<'a>(&'a self, fonts: &'a Fonts) -> Vec<ResolvedStyle<'a>> {
+        let own = ResolvedStyle {
+            style: self.style,
+            chain: font_chain(fonts, &self.style),
+            image: None,
+        };
+        let spans = self.spans.iter().map(|span| ResolvedStyle {
+            style: span.style,
+            chain: font_chain(fonts, &span.style),
+            image: span.image.as_ref().map(|image| {
+                let height = span.style.size;
+                let size = image
+                    .size
+                    .unwrap_or_else(|| match fonts.image(&image.name) {
+                        Some(glyph) if glyph.region.height > 0 => Size::from_wh(
+                            height * glyph.region.width as Scalar / glyph.region.height as Scalar,
+                            height,
+                        ),
+                        _ => Size::from_wh(height, height),
+                    });
+                (image.name.as_str(), size)
+            }),
+        });
+        std::iter::once(own).chain(spans).collect()
+    }
+
+    // The text split into ranges of one style, in order and covering all of it
+    fn segments(&self) -> Vec<(Range<usize>, usize)> {
+        let mut spans: Vec<(usize, &TextSpan)> = self.spans.iter().enumerate().collect();
+        spans.sort_by_key(|(_, span)| span.range.start);
+        let mut segments = Vec::new();
+        let mut pos = 0;
+        for (index, span) in spans {
+            let start = span.range.start.max(pos);
+            let end = span.range.end.min(self.text.len());
+            if start >= end
+                || !self.text.is_char_boundary(start)
+                || !self.text.is_char_boundary(end)
+            {
+                continue;
+            }
+            if pos < start {
+                segments.push((pos..start, 0));
+            }
+            segments.push((start..end, index + 1));
+            pos = end;
+        }
+        if pos < self.text.len() {
+            segments.push((pos..self.text.len(), 0));
+        }
+        segments
+    }
+
+    /// The size of the text when laid out in `max_width`, or on unbounded lines if `None`
+    ///
+    pub fn measure(&self, fonts: &Fonts, max_width: Option<Scalar>) -> Size {
+        self.layout(fonts, max_width).size
+    }
+
+    /// Break the text into lines and place its glyphs. `max_width` is the width lines wrap,
+    /// align and truncate in. Text in a font that is not loaded lays out as nothing.
+    ///
+    /// Paragraphs are split into runs of one bidi level, script and span, and each run is
+    /// shaped on its own. Lines are broken in logical order, then reordered for display. Each
+    /// line is tall enough for the largest style on it.
+    ///
+    pub fn layout(&self, fonts: &Fonts, max_width: Option<Scalar>) -> TextLayout {
+        let paragraph = &self.paragraph;
+        let styles = self.resolve_styles(fonts);
+        let segments = self.segments();
+        let (font_key, font) = match styles[0].chain.first() {
+            Some(first) => *first,
+            None => return TextLayout::default(),
+        };
+        let wrap_width = max_width.filter(|_| paragraph.wrap);
+
+        let mut shaped = Vec::new();
+        let mut breaks = Vec::new();
+        let mut start = 0;
+        for raw in self.text.split('\n') {
+            let line = raw.strip_suffix('\r').unwrap_or(raw);
+            let first = shaped.len();
+            let level = shape_paragraph(
+                &styles,
+                &segments,
+                paragraph.direction,
+                line,
+                start,
+                &mut shaped,
+            );
+            break_lines(&shaped, first, line, start, level, wrap_width, &mut breaks);
+            start += raw.len() + 1;
+        }
+        // the line feeds belong to the lines they end
+        for i in 1..breaks.len() {
+            breaks[i - 1].text.end = breaks[i].text.start;
+        }
+        if let Some(last) = breaks.last_mut() {
+            last.text.end = self.text.len();
+        }
+
+        let mut truncated = false;
+        if let Some(max_lines) = paragraph.max_lines {
+            if breaks.len() > max_lines {
+                breaks.truncate(max_lines.max(1));
+                truncated = true;
+            }
+        }
+
+        let extents: Vec<(Scalar, Scalar)> = styles
+            .iter()
+            .map(|style| style.extents(&paragraph.line_height))
+            .collect();
+        let ellipsis = ellipsis_glyphs(font_key, font, &self.style);
+        let ellipsis_width: Scalar = ellipsis.iter().map(|g| g.advance).sum();
+
+        let last = breaks.len() - 1;
+        let cut: Vec<bool> = breaks
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                paragraph.overflow == TextOverflow::Ellipsis
+                    && ((truncated && i == last) || max_width.is_some_and(|m| b.width > m))
+            })
+            .collect();
+        if cut.iter().any(|c| *c) {
+            truncated = true;
+        }
+
+        let mut layout = TextLayout {
+            truncated,
+            ..Default::default()
+        };
+        let mut placed = Vec::with_capacity(breaks.len());
+        for (b, cut) in breaks.iter().zip(&cut) {
+            let (end, width) = if *cut {
+                let available = max_width.map_or(Scalar::INFINITY, |m| m - ellipsis_width);
+                let (end, width) = fit_glyphs(&shaped, b.glyphs.clone(), available);
+                (end, width + ellipsis_width)
+            } else {
+                (b.glyphs.end, b.width)
+            };
+            placed.push((end, width));
+        }
+        let widest = placed
+            .iter()
+            .fold(0.0, |w: Scalar, (_, width)| w.max(*width));
+        let box_width = max_width.unwrap_or(widest);
+
+        let mut top = 0.0;
+        for ((b, cut), (end, width)) in breaks.iter().zip(&cut).zip(&placed) {
+            let rtl = b.level % 2 == 1;
+            let free = box_width - width;
+            let left = match (paragraph.align, rtl) {
+                (TextAlign::Left, _)
+                | (TextAlign::Start | TextAlign::Justify, false)
+                | (TextAlign::End, true) => 0.0,
+                (TextAlign::Center, _) => free / 2.0,
+                _ => free,
+            };
+
+            // the line in logical order, ending in the ellipsis if it is cut short
+            let mut items = shaped[b.glyphs.start..*end].to_vec();
+            let content_len = items
+                .iter()
+                .rposition(|g| !g.whitespace)
+                .map_or(0, |i| i + 1);
+            // trailing spaces take the paragraph direction, UAX #9 rule L1
+            let mut trailing = 0.0;
+            for g in &mut items[content_len..] {
+                g.level = b.level;
+                trailing += g.advance;
+            }
+            let visible = items.len();
+            let caret_end = if *cut {
+                let cluster = shaped.get(*end).map_or(b.text.end, |g| g.cluster);
+                items.extend(ellipsis.iter().map(|g| ShapedGlyph {
+                    cluster,
+                    level: b.level,
+                    ..*g
+                }));
+                cluster
+            } else {
+                let text = &self.text[..b.text.end];
+                let text = text.strip_suffix('\n').unwrap_or(text);
+                text.strip_suffix('\r').unwrap_or(text).len()
+            };
+
+            let justify = paragraph.align == TextAlign::Justify && b.wrapped && !cut && free > 0.0;
+            let spaces = items[..content_len].iter().filter(|g| g.whitespace).count();
+            let extra = if justify && spaces > 0 {
+                free / spaces as Scalar
+            } else {
+                0.0
+            };
+
+            let (above, below) = items.iter().fold(extents[0], |(above, below), g| {
+                let (a, b) = extents[g.style];
+                (above.max(a), below.max(b))
+            });
+            let line_height = above + below;
+            let baseline = top + above;
+            let first = layout.glyphs.len();
+            // trailing spaces of a right to left line hang off its left edge
+            let mut x = if rtl { left - trailing } else { left };
+            let mut spans = vec![(0.0, 0.0); items.len()];
+            for i in visual_order(&items) {
+                let g = &items[i];
+                let style = &styles[g.style];
+                match style.image {
+                    Some((name, size)) => layout.images.push(PositionedImage {
+                        name: name.to_string(),
+                        rect: Rect::from_pos_and_size(
+                            Point::from_xy(x, baseline - size.height),
+                            size,
+                        ),
+                        color: style.style.color,
+                        cluster: g.cluster,
+                    }),
+                    None => {
+                        let position = Point::from_xy(x + g.offset.x, baseline + g.offset.y);
+                        layout.glyphs.push(positioned(g, &style.style, position));
+                    }
+                }
+                let advance = if g.whitespace && i < content_len {
+                    g.advance + extra
+                } else {
+                    g.advance
+                };
+                spans[i] = (x, x + advance);
+                x += advance;
+            }
+
+            layout.lines.push(TextLine {
+                text_range: b.text.clone(),
+                glyph_range: first..layout.glyphs.len(),
+                top,
+                height: line_height,
+                baseline,
+                left,
+                width: if justify && spaces > 0 {
+                    box_width
+                } else {
+                    *width
+                },
+                rtl,
+                carets: caret_stops(
+                    &self.text,
+                    &items[..visible],
+                    &spans[..visible],
+                    caret_end,
+                    left,
+                ),
+            });
+            top += line_height;
+        }
+        layout.size = Size::from_wh(widest, top);
+        layout
+    }
+}
+
+fn positioned(g: &ShapedGlyph, style: &TextStyle, position: Point) -> PositionedGlyph {
+    PositionedGlyph {
+        font: g.font,
+        glyph: g.glyph,
+        size: style.size,
+        color: style.color,
+        position,
+        advance: g.advance,
+        cluster: g.cluster,
+        rtl: g.level % 2 == 1,
+    }
+}
+
+// Shape one line feed delimited paragraph, appending its glyphs in logical order. Returns the
+// paragraph's bidi level.
+fn shape_paragraph(
+    styles: &[ResolvedStyle],
+    segments: &[(Range<usize>, usize)],
+    direction: TextDirection,
+    text: &str,
+    base: usize,
+    out: &mut Vec<ShapedGlyph>,
+) -> u8 {
+    let default_level = match direction {
+        TextDirection::Auto => None,
+        TextDirection::LeftToRight => Some(Level::ltr()),
+        TextDirection::RightToLeft => Some(Level::rtl()),
+    };
+    let bidi = BidiInfo::new(text, default_level);
+    let level = bidi.paragraphs.first().map_or_else(
+        || default_level.map_or(0, |level| level.number()),
+        |paragraph| paragraph.level.number(),
+    );
+
+    let first = out.len();
+    for run in runs(text, &bidi.levels) {
+        let level = bidi.levels[run.start].number();
+        let run = base + run.start..base + run.end;
+        for (segment, index) in segments {
+            let start = segment.start.max(run.start);
+            let end = segment.end.min(run.end);
+            if start >= end {
+                continue;
+            }
+            let style = &styles[*index];
+            let text = &text[start - base..end - base];
+            match style.image {
+                Some((_, size)) => out.extend(text.char_indices().map(|(i, _)| ShapedGlyph {
+                    font: FontKey::default(),
+                    glyph: 0,
+                    cluster: start + i,
+                    advance: size.width,
+                    offset: Point::default(),
+                    whitespace: false,
+                    level,
+                    style: *index,
+                })),
+                None => shape_run(&style.chain, &style.style, *index, text, start, level, out),
+            }
+        }
+    }
+    // letter spacing goes after each cluster
+    for i in first..out.len() {
+        if out
+            .get(i + 1)
+            .is_none_or(|next| next.cluster != out[i].cluster)
+        {
+            out[i].advance += styles[out[i].style].style.letter_spacing;
+        }
+    }
+    level
+}
+
+// Split a paragraph where the bidi level or the script changes. Characters common to all
+// scripts, such as spaces and punctuation, join the run they are in.
+fn runs(text: &str, levels: &[Level]) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut run_script = None;
+    for (i, c) in text.char_indices() {
+        let script = Some(c.script())
+            .filter(|s| !matches!(s, Script::Common | Script::Inherited | Script::Unknown));
+        let script_changed = script.is_some() && run_script.is_some() && script != run_script;
+        if i > start && (levels[i] != levels[start] || script_changed) {
+            runs.push(start..i);
+            start = i;
+            run_script = None;
+        }
+        if run_script.is_none() {
+            run_script = script;
+        }
+    }
+    if start < text.len() {
+        runs.push(start..text.len());
+    }
+    runs
+}
+
+// The fonts to lay out a style with, in fallback order. Fonts that are not loaded are skipped.
+fn font_chain<'a>(fonts: &'a Fonts, style: &TextStyle) -> Vec<(FontKey, &'a Font)> {
+    let keys = match style.collection.and_then(|key| fonts.collection(key)) {
+        Some(collection) => collection.fonts(style.font_style),
+        None => std::slice::from_ref(&style.font),
+    };
+    keys.iter()
+        .filter_map(|key| Some((*key, fonts.font(*key)?)))
+        .collect()
+}
+
+// Shape a run of one level and script, appending its glyphs in logical order. Clusters the
+// first font of `chain` has no glyph for are shaped again with the rest of the chain. The last
+// font draws what none of them have.
+fn shape_run(
+    chain: &[(FontKey, &Font)],
+    style: &TextStyle,
+    style_index: usize,
+    text: &str,
+    base: usize,
+    level: u8,
+    out: &mut Vec<ShapedGlyph>,
+) {
+    let (key, font) = match chain.first() {
+        Some(first) => *first,
+        None => return,
+    };
+    let face = match rustybuzz::Face::from_slice(font.font_data(), 0) {
+        Some(face) => face,
+        None => return shape_run(&chain[1..], style, style_index, text, base, level, out),
+    };
+    let rtl = level % 2 == 1;
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(if rtl {
+        Direction::RightToLeft
+    } else {
+        Direction::LeftToRight
+    });
+    let glyphs = rustybuzz::shape(&face, &[], buffer);
+    let scale = style.size / face.units_per_em() as Scalar;
+
+    let mut shaped: Vec<ShapedGlyph> = glyphs
+        .glyph_infos()
+        .iter()
+        .zip(glyphs.glyph_positions())
+        .map(|(info, position)| {
+            let cluster = info.cluster as usize;
+            ShapedGlyph {
+                font: key,
+                glyph: info.glyph_id as u16,
+                cluster: base + cluster,
+                advance: position.x_advance as Scalar * scale,
+                offset: Point::from_xy(
+                    position.x_offset as Scalar * scale,
+                    -position.y_offset as Scalar * scale,
+                ),
+                whitespace: text[cluster..].starts_with(char::is_whitespace),
+                level,
+                style: style_index,
+            }
+        })
+        .collect();
+    // right to left runs come out of the shaper in display order
+    if rtl {
+        shaped.reverse();
+    }
+    if chain.len() == 1 {
+        out.extend(shaped);
+        return;
+    }
+
+    // the end of the cluster starting at `i`, and whether the font is missing any of it
+    let cluster_at = |i: usize| {
+        let next = (i..shaped.len())
+            .find(|j| shaped[*j].cluster != shaped[i].cluster)
+            .unwrap_or(shaped.len());
+        (next, shaped[i..next].iter().any(|g| g.glyph == 0))
+    };
+    let mut i = 0;
+    while i < shaped.len() {
+        let (next, missing) = cluster_at(i);
+        if !missing {
+            out.extend_from_slice(&shaped[i..next]);
+            i = next;
+            continue;
+        }
+        let start = shaped[i].cluster;
+        i = next;
+        while i < shaped.len() {
+            let (next, missing) = cluster_at(i);
+            if !missing {
+                break;
+            }
+            i = next;
+        }
+        let end = shaped.get(i).map_or(base + text.len(), |g| g.cluster);
+        shape_run(
+            &chain[1..],
+            style,
+            style_index,
+            &text[start - base..end - base],
+            start,
+            level,
+            out,
+        );
+    }
+}
+
+// Indices of `items` in display order. Runs at each level, from the highest down to the lowest
+// odd level, are reversed, as in UAX #9 rule L2.
+fn visual_order(items: &[ShapedGlyph]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    let highest = items.iter().map(|g| g.level).max().unwrap_or(0);
+    let lowest_odd = match items.iter().map(|g| g.level).filter(|l| l % 2 == 1).min() {
+        Some(level) => level,
+        None => return order,
+    };
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if items[order[i]].level < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && items[order[i]].level >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+    order
+}
+
+// Caret positions of a line's glyphs, given the span each covers on screen. A caret sits on
+// the leading edge of its grapheme, which is the right edge in right to left runs. Graphemes
+// that share a glyph, as in a ligature, divide it evenly.
+fn caret_stops(
+    text: &str,
+    items: &[ShapedGlyph],
+    spans: &[(Scalar, Scalar)],
+    end: usize,
+    empty_x: Scalar,
+) -> Vec<(usize, Scalar)> {
+    let mut stops = Vec::new();
+    let mut end_x = empty_x;
+    let mut i = 0;
+    while i < items.len() {
+        let cluster = items[i].cluster;
+        let next = (i..items.len())
+            .find(|j| items[*j].cluster != cluster)
+            .unwrap_or(items.len());
+        let x0 = spans[i..next]
+            .iter()
+            .fold(Scalar::INFINITY, |x, s| x.min(s.0));
+        let x1 = spans[i..next]
+            .iter()
+            .fold(Scalar::NEG_INFINITY, |x, s| x.max(s.1));
+        let rtl = items[i].level % 2 == 1;
+        let cluster_end = items.get(next).map_or(end, |g| g.cluster).max(cluster);
+
+        let graphemes: Vec<usize> = text[cluster..cluster_end]
+            .grapheme_indices(true)
+            .map(|(offset, _)| cluster + offset)
+            .collect();
+        let count = graphemes.len().max(1) as Scalar;
+        for (k, byte) in graphemes.iter().enumerate() {
+            let t = (x1 - x0) * k as Scalar / count;
+            stops.push((*byte, if rtl { x1 - t } else { x0 + t }));
+        }
+        end_x = if rtl { x0 } else { x1 };
+        i = next;
+    }
+    stops.push((end, end_x));
+    stops
+}
+
+fn ellipsis_glyphs(key: FontKey, font: &Font, style: &TextStyle) -> Vec<ShapedGlyph> {
+    let (c, count) = match font.glyph_id('\u{2026}') {
+        Some(_) => ('\u{2026}', 1),
+        None => ('.', 3),
+    };
+    let glyph = font.glyph_id(c).unwrap_or(0);
+    let g = ShapedGlyph {
+        font: key,
+        glyph,
+        cluster: 0,
+        advance: font.advance(glyph, style.size) + style.letter_spacing,
+        offset: Point::default(),
+        whitespace: false,
+        level: 0,
+        style: 0,
+    };
+    vec![g; count]
+}
+
+// The glyphs from the start of `range` that fit in `available`, without trailing spaces.
+// Graphemes are kept whole.
+fn fit_glyphs(shaped: &[ShapedGlyph], range: Range<usize>, available: Scalar) -> (usize, Scalar) {
+    let (mut end, mut width) = (range.start, 0.0);
+    let mut pen = 0.0;
+    let mut i = range.start;
+    while i < range.end {
+        let cluster = shaped[i].cluster;
+        let next = (i..range.end)
+            .find(|j| shaped[*j].cluster != cluster)
+            .unwrap_or(range.end);
+        let advance: Scalar = shaped[i..next].iter().map(|g| g.advance).sum();
+        if pen + advance > available {
+            break;
+        }
+        pen += advance;
+        if !shaped[i].whitespace {
+            end = next;
+            width = pen;
+        }
+        i = next;
+    }
+    (end, width)
+}
+
+// Greedy line breaking of one line feed delimited paragraph. Breaks are allowed before a
+// word that follows white space, and spaces at the end of a line hang past the width.
+fn break_lines(
+    shaped: &[ShapedGlyph],
+    first: usize,
+    text: &str,
+    base: usize,
+    level: u8,
+    max_width: Option<Scalar>,
+    out: &mut Vec<LineBreak>,
+) {
+    let mut glyph = first;
+    let mut line_glyphs = first;
+    let mut line_text = base;
+    let mut pen = 0.0;
+    let mut width = 0.0;
+    let mut has_content = false;
+
+    for (offset, word) in text.split_word_bound_indices() {
+        let word_start = glyph;
+        let word_end = base + offset + word.len();
+        while glyph < shaped.len() && shaped[glyph].cluster < word_end {
+            glyph += 1;
+        }
+        let word_width: Scalar = shaped[word_start..glyph].iter().map(|g| g.advance).sum();
+        if word.chars().all(char::is_whitespace) {
+            pen += word_width;
+            continue;
+        }
+
+        if let Some(max_width) = max_width {
+            if has_content && pen + word_width > max_width {
+                out.push(LineBreak {
+                    glyphs: line_glyphs..word_start,
+                    text: line_text..base + offset,
+                    width,
+                    wrapped: true,
+                    level,
+                });
+                line_glyphs = word_start;
+                line_text = base + offset;
+                pen = 0.0;
+                has_content = false;
+            }
+            if word_width > max_width {
+                // no word boundary fits, break between graphemes
+                let mut i = word_start;
+                while i < glyph {
+                    let cluster = shaped[i].cluster;
+                    let next = (i..glyph)
+                        .find(|j| shaped[*j].cluster != cluster)
+                        .unwrap_or(glyph);
+                    let advance: Scalar = shaped[i..next].iter().map(|g| g.advance).sum();
+                    if has_content && pen + advance > max_width {
+                        out.push(LineBreak {
+                            glyphs: line_glyphs..i,
+                            text: line_text..cluster,
+                            width,
+                            wrapped: true,
+                            level,
+                        });
+                        line_glyphs = i;
+                        line_text = cluster;
+                        pen = 0.0;
+                    }
+                    pen += advance;
+                    width = pen;
+                    has_content = true;
+                    i = next;
+                }
+                continue;
+            }
+        }
+        pen += word_width;
+        width = pen;
+        has_content = true;
+    }
+
+    out.push(LineBreak {
+        glyphs: line_glyphs..glyph.max(line_glyphs),
+        text: line_text..base + text.len(),
+        width,
+        wrapped: false,
+        level,
+    });
+}
+
+/// Build textured quads for laid out text, one geometry per atlas page. `origin` is the top
+/// left of the text box in layout units and `scale` the physical pixels per unit. Pen
+/// positions are snapped to physical pixels so glyphs are sampled texel for texel.
+///
+pub(crate) fn tessellate_text(
+    out: &mut Vec<(usize, Geometry)>,
+    layout: &TextLayout,
+    origin: Point,
+    fonts: &mut Fonts,
+    scale: Scalar,
+) {
+    for g in &layout.glyphs {
+        let atlas_glyph = match fonts.glyph(g.font, g.glyph, g.size * scale) {
+            Some(atlas_glyph) => atlas_glyph,
+            None => continue,
+        };
+        let pen = (origin + g.position) * scale;
+        let pen = Point::from_xy(pen.x.round(), pen.y.round());
+        let rect = Rect::from_pos_and_size(
+            (pen + atlas_glyph.offset) / scale,
+            Size::from_wh(
+                atlas_glyph.region.width as Scalar / scale,
+                atlas_glyph.region.height as Scalar / scale,
+            ),
+        );
+        // color glyphs keep their own colors, only taking the text's opacity
+        let color = match atlas_glyph.format {
+            AtlasFormat::Rgba => opacity(g.color),
+            AtlasFormat::Alpha => g.color,
+        };
+        page_geometry(out, atlas_glyph.page).add_textured_rect(&rect, &atlas_glyph.uv, color);
+    }
+
+    for image in &layout.images {
+        let atlas_image = match fonts.image(&image.name) {
+            Some(atlas_image) => atlas_image,
+            None => continue,
+        };
+        let corner = (origin + image.rect.point) * scale;
+        let rect = Rect::from_pos_and_size(
+            Point::from_xy(corner.x.round(), corner.y.round()) / scale,
+            image.rect.size,
+        );
+        page_geometry(out, atlas_image.page).add_textured_rect(
+            &rect,
+            &atlas_image.uv,
+            opacity(image.color),
+        );
+    }
+}
+
+// The geometry drawn with an atlas page, added if this is the page's first use
+fn page_geometry(out: &mut Vec<(usize, Geometry)>, page: usize) -> &mut Geometry {
+    let index = match out.iter().position(|(p, _)| *p == page) {
+        Some(index) => index,
+        None => {
+            out.push((page, Geometry::default()));
+            out.len() - 1
+        }
+    };
+    &mut out[index].1
+}
+
+// White with the alpha of `color`, for drawing RGBA texels as they are
+fn opacity(color: CluiColor) -> CluiColor {
+    CluiColor {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: color.a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::font::{test_font_data, test_light_font_data, FontCollection, FontStyle, Fonts};
+    use crate::text::{
+        tessellate_text, CluiText, InlineImage, LineHeight, TextAlign, TextDirection, TextOverflow,
+        TextStyle,
+    };
+    use crate::{CluiColor, Point, Size};
+
+    fn fonts() -> (Fonts, TextStyle) {
+        let mut fonts = Fonts::new();
+        let font = fonts.add_font("DejaVuSans.ttf", test_font_data()).unwrap();
+        let style = TextStyle {
+            font,
+            size: 20.0,
+            ..Default::default()
+        };
+        (fonts, style)
+    }
+
+    fn line_texts<'a>(text: &'a CluiText, fonts: &Fonts, width: Option<f32>) -> Vec<&'a str> {
+        text.layout(fonts, width)
+            .lines
+            .iter()
+            .map(|l| text.text[l.text_range.clone()].trim_end())
+            .collect()
+    }
+
+    #[test]
+    fn measure_single_line() {
+        let (fonts, style) = fonts();
+        let text = CluiText::new("Hello", style);
+        let layout = text.layout(&fonts, None);
+        assert_eq!(layout.lines.len(), 1);
+        assert_eq!(layout.glyphs.len(), 5);
+        let line_height = fonts.font(style.font).unwrap().metrics(20.0).line_height();
+        assert_eq!(layout.size.height, line_height);
+        let advances: f32 = layout.glyphs.iter().map(|g| g.advance).sum();
+        assert_eq!(layout.size.width, advances);
+        assert!(layout
+            .glyphs
+            .windows(2)
+            .all(|w| w[0].position.x < w[1].position.x));
+        assert_eq!(layout.glyphs[1].cluster, 1);
+
+        // letter spacing is added after every grapheme
+        let spaced = CluiText::new(
+            "Hello",
+            TextStyle {
+                letter_spacing: 2.0,
+                ..style
+            },
+        );
+        let spaced_width = spaced.measure(&fonts, None).width;
+        assert!((spaced_width - layout.size.width - 10.0).abs() < 0.001);
+
+        // an empty text is still one line tall
+        let empty = CluiText::new("", style).layout(&fonts, None);
+        assert_eq!(empty.size.height, line_height);
+        assert_eq!(empty.lines.len(), 1);
+
+        // unknown fonts lay out as nothing
+        assert_eq!(
+            CluiText::new("Hello", TextStyle::default()).measure(&fonts, None),
+            Default::default()
+        );
+    }
+
+    #[test]
+    fn wraps_at_words_and_graphemes() {
+        let (fonts, style) = fonts();
+        let text = CluiText::new("the quick brown fox", style);
+        let one_line = text.measure(&fonts, None).width;
+        let lines = line_texts(&text, &fonts, Some(one_line * 0.6));
+        assert_eq!(lines, vec!["the quick", "brown fox"]);
+        let layout = text.layout(&fonts, Some(one_line * 0.6));
+        assert!(layout.lines.iter().all(|l| l.width <= one_line * 0.6));
+        assert_eq!(layout.lines[1].top, layout.lines[0].height);
+
+        // line feeds always break, and wrapping can be turned off
+        let mut text = CluiText::new("one two\nthree", style);
+        text.paragraph.wrap = false;
+        assert_eq!(
+            line_texts(&text, &fonts, Some(1.0)),
+            vec!["one two", "three"]
+        );
+        let layout = text.layout(&fonts, None);
+        assert_eq!(layout.lines[0].text_range, 0..8);
+
+        // a word wider than the line is broken between graphemes
+        let long = CluiText::new("oooooooo", style);
+        let width = long.measure(&fonts, None).width;
+        let lines = line_texts(&long, &fonts, Some(width / 2.0 + 1.0));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines, vec!["oooo", "oooo"]);
+
+        // combining marks stay with their base
+        let marks = CluiText::new("e\u{301}e\u{301}", style);
+        let width = marks.measure(&fonts, None).width;
+        let lines = line_texts(&marks, &fonts, Some(width * 0.6));
+        assert_eq!(lines, vec!["e\u{301}", "e\u{301}"]);
+    }
+
+    #[test]
+    fn alignment() {
+        let (fonts, style) = fonts();
+        let mut text = CluiText::new("ab", style);
+        let width = text.measure(&fonts, None).width;
+
+        text.paragraph.align = TextAlign::Center;
+        let layout = text.layout(&fonts, Some(width + 40.0));
+        assert!((layout.lines[0].left - 20.0).abs() < 0.001);
+        assert_eq!(layout.glyphs[0].position.x, layout.lines[0].left);
+
+        text.paragraph.align = TextAlign::Right;
+        let layout = text.layout(&fonts, Some(width + 40.0));
+        assert!((layout.glyphs[0].position.x - 40.0).abs() < 0.001);
+        // without a width lines align against the widest
+        assert_eq!(text.layout(&fonts, None).glyphs[0].position.x, 0.0);
+
+        // justified lines fill the width, except the last
+        let mut text = CluiText::new("aa bb cc dd", style);
+        text.paragraph.align = TextAlign::Justify;
+        let width = text.measure(&fonts, None).width * 0.7;
+        let layout = text.layout(&fonts, Some(width));
+        assert_eq!(layout.lines.len(), 2);
+        let first = &layout.lines[0];
+        let last_glyph = &layout.glyphs[first.glyph_range.end - 2];
+        assert!((last_glyph.position.x + last_glyph.advance - width).abs() < 0.001);
+        assert_eq!(first.width, width);
+        assert!(layout.lines[1].width < width);
+    }
+
+    #[test]
+    fn line_height() {
+        let (fonts, style) = fonts();
+        let mut text = CluiText::new("a\nb", style);
+        text.paragraph.line_height = LineHeight::Relative(2.0);
+        let layout = text.layout(&fonts, None);
+        assert_eq!(layout.size.height, 80.0);
+        assert_eq!(layout.lines[1].top, 40.0);
+        // the extra space is split above and below the line
+        let metrics = fonts.font(style.font).unwrap().metrics(20.0);
+        let half_leading = (40.0 - metrics.ascent - metrics.descent) / 2.0;
+        assert_eq!(layout.lines[0].baseline, half_leading + metrics.ascent);
+
+        text.paragraph.line_height = LineHeight::Fixed(25.0);
+        assert_eq!(text.measure(&fonts, None).height, 50.0);
+    }
+
+    #[test]
+    fn ellipsis() {
+        let (fonts, style) = fonts();
+        let mut text = CluiText::new("truncate this label", style);
+        text.paragraph.wrap = false;
+        text.paragraph.overflow = TextOverflow::Ellipsis;
+        let width = text.measure(&fonts, None).width;
+
+        let layout = text.layout(&fonts, Some(width / 2.0));
+        assert!(layout.truncated);
+        assert!(layout.size.width <= width / 2.0);
+        let font = fonts.font(style.font).unwrap();
+        assert_eq!(
+            layout.glyphs.last().unwrap().glyph,
+            font.glyph_id('\u{2026}').unwrap()
+        );
+        // it fits, so nothing is cut
+        assert!(!text.layout(&fonts, Some(width)).truncated);
+
+        // wrapped text is cut at the line limit
+        text.paragraph.wrap = true;
+        text.paragraph.max_lines = Some(2);
+        let layout = text.layout(&fonts, Some(width * 0.4));
+        assert_eq!(layout.lines.len(), 2);
+        assert!(layout.truncated);
+        assert_eq!(
+            layout.glyphs.last().unwrap().glyph,
+            font.glyph_id('\u{2026}').unwrap()
+        );
+
+        // with clipping the lines are dropped without an ellipsis
+        text.paragraph.overflow = TextOverflow::Clip;
+        let layout = text.layout(&fonts, Some(width * 0.4));
+        assert_eq!(layout.lines.len(), 2);
+        assert_ne!(
+            layout.glyphs.last().unwrap().glyph,
+            font.glyph_id('\u{2026}').unwrap()
+        );
+    }
+
+    #[test]
+    fn complex_shaping() {
+        let (fonts, style) = fonts();
+        let font = fonts.font(style.font).unwrap();
+
+        // kerning pulls the V under the A
+        let text = CluiText::new("AV", style);
+        let layout = text.layout(&fonts, None);
+        let a = font.glyph_id('A').unwrap();
+        let v = font.glyph_id('V').unwrap();
+        assert!(layout.size.width < font.advance(a, 20.0) + font.advance(v, 20.0) - 0.5);
+
+        // arabic letters take their joined forms, and the first one is drawn rightmost
+        let text = CluiText::new("سلام", style);
+        let layout = text.layout(&fonts, None);
+        let isolated = font.glyph_id('س').unwrap();
+        let first = layout.glyphs.iter().find(|g| g.cluster == 0).unwrap();
+        assert_ne!(first.glyph, isolated);
+        assert!(first.rtl);
+        assert!(layout
+            .glyphs
+            .iter()
+            .all(|g| g.position.x <= first.position.x));
+    }
+
+    #[test]
+    fn bidi_reordering() {
+        let (fonts, style) = fonts();
+        let text = CluiText::new("abc אבג def", style);
+        let layout = text.layout(&fonts, None);
+        let clusters: Vec<usize> = layout.glyphs.iter().map(|g| g.cluster).collect();
+        // the hebrew letters are two bytes each, and read right to left
+        assert_eq!(clusters, vec![0, 1, 2, 3, 8, 6, 4, 10, 11, 12, 13]);
+        assert!(!layout.lines[0].rtl);
+
+        // a right to left paragraph aligns right
+        let mut text = CluiText::new("אבג", style);
+        let layout = text.layout(&fonts, Some(200.0));
+        assert!(layout.lines[0].rtl);
+        assert_eq!(layout.lines[0].left, 200.0 - layout.lines[0].width);
+        text.paragraph.direction = TextDirection::LeftToRight;
+        assert_eq!(text.layout(&fonts, Some(200.0)).lines[0].left, 0.0);
+        text.paragraph.align = TextAlign::Left;
+        text.paragraph.direction = TextDirection::RightToLeft;
+        assert_eq!(text.layout(&fonts, Some(200.0)).lines[0].left, 0.0);
+    }
+
+    #[test]
+    fn carets() {
+        let (fonts, style) = fonts();
+        let text = CluiText::new("אבג", style);
+        let layout = text.layout(&fonts, None);
+        let width = layout.size.width;
+
+        // the start of right to left text is on the right
+        assert_eq!(layout.caret_rect(0).unwrap().point.x, width);
+        assert_eq!(layout.caret_rect(6).unwrap().point.x, 0.0);
+        assert_eq!(layout.caret_rect(1), None);
+        assert_eq!(layout.caret_left(0), 2);
+        assert_eq!(layout.caret_left(6), 6);
+        assert_eq!(layout.caret_right(2), 0);
+        assert_eq!(layout.caret_right(0), 0);
+        assert_eq!(layout.hit_test(Point::from_xy(width + 10.0, 5.0)), 0);
+        assert_eq!(layout.hit_test(Point::from_xy(-10.0, 5.0)), 6);
+
+        // moving off the end of a line goes to the next one
+        let text = CluiText::new("ab\ncd", style);
+        let layout = text.layout(&fonts, None);
+        assert_eq!(layout.caret_right(1), 2);
+        assert_eq!(layout.caret_right(2), 3);
+        assert_eq!(layout.caret_left(3), 2);
+        assert!(layout.caret_rect(3).unwrap().point.y > 0.0);
+        assert_eq!(layout.hit_test(Point::from_xy(0.0, 1000.0)), 3);
+    }
+
+    #[test]
+    fn font_fallback() {
+        let (mut fonts, style) = fonts();
+        let sans = style.font;
+        let light = fonts.add_font("light.ttf", test_light_font_data()).unwrap();
+        assert_eq!(fonts.font(light).unwrap().glyph_id('א'), None);
+
+        let mut collection = FontCollection::new("ui");
+        collection.set_fonts(FontStyle::Regular, vec![light, sans]);
+        let collection = fonts.add_collection(collection);
+        let style = TextStyle {
+            collection: Some(collection),
+            ..style
+        };
+
+        // the hebrew comes from the fallback, the rest from the primary font
+        let text = CluiText::new("ab אב", style);
+        let layout = text.layout(&fonts, None);
+        let from: Vec<bool> = layout.glyphs.iter().map(|g| g.font == sans).collect();
+        assert_eq!(from, [false, false, false, true, true]);
+        assert!(layout.glyphs.iter().all(|g| g.glyph != 0));
+        assert!(layout.glyphs[3].rtl);
+
+        // with no fallback the missing glyphs are drawn by the primary font
+        fonts
+            .collection_mut(collection)
+            .unwrap()
+            .set_fonts(FontStyle::Regular, vec![light]);
+        let layout = text.layout(&fonts, None);
+        assert!(layout.glyphs.iter().all(|g| g.font == light));
+        assert_eq!(layout.glyphs[4].glyph, 0);
+    }
+
+    #[test]
+    fn styled_spans() {
+        let (fonts, style) = fonts();
+        let red = CluiColor {
+            r: 1.0,
+            a: 1.0,
+            ..Default::default()
+        };
+        let mut text = CluiText::new("ab ", style);
+        text.push(
+            "cd",
+            TextStyle {
+                color: red,
+                ..style
+            },
+        );
+        let plain = text.layout(&fonts, None);
+        assert_eq!(plain.glyphs[3].color, red);
+        assert_eq!(plain.glyphs[2].color, style.color);
+
+        // a larger span makes its line taller and moves the baseline down
+        text.push(
+            "E",
+            TextStyle {
+                size: 40.0,
+                ..style
+            },
+        );
+        text.text.push_str("\nf");
+        let big = text.layout(&fonts, None);
+        assert_eq!(big.glyphs[5].size, 40.0);
+        assert!(big.lines[0].height > plain.lines[0].height * 1.9);
+        assert!(big.lines[0].baseline > plain.lines[0].baseline);
+        assert!((big.lines[1].height - plain.lines[0].height).abs() < 0.001);
+        // the unstyled text after the span is back in the text's own style
+        assert_eq!(big.glyphs[6].size, 20.0);
+    }
+
+    #[test]
+    fn inline_images() {
+        let (mut fonts, style) = fonts();
+        fonts.add_image("fire", 8, 4, vec![255; 8 * 4 * 4]).unwrap();
+        let mut text = CluiText::from_markup("a <icon=fire/> b", style, &fonts).unwrap();
+        text.push_image(
+            InlineImage {
+                name: "missing".to_string(),
+                size: Some(Size::from_wh(5.0, 30.0)),
+            },
+            style,
+        );
+
+        let layout = text.layout(&fonts, None);
+        assert_eq!(layout.glyphs.len(), 4);
+        assert_eq!(layout.images.len(), 2);
+        let fire = &layout.images[0];
+        let line = &layout.lines[0];
+        // as tall as the font size, keeping the aspect ratio, standing on the baseline
+        assert_eq!(fire.rect.size, Size::from_wh(40.0, 20.0));
+        assert_eq!(fire.rect.point.y + 20.0, line.baseline);
+        assert_eq!(fire.cluster, 2);
+        let b = layout.glyphs.iter().find(|g| g.cluster == 6).unwrap();
+        assert!(b.position.x > fire.rect.point.x + 40.0);
+        // the tall image makes room for itself above the baseline
+        assert!(line.baseline >= 30.0);
+        assert!(layout.caret_rect(5).is_some());
+
+        // images are drawn from the atlas, and ones that were never added are skipped
+        let mut out = Vec::new();
+        tessellate_text(&mut out, &layout, Point::default(), &mut fonts, 1.0);
+        let page = fonts.image("fire").unwrap().page;
+        let (_, geometry) = out.iter().find(|(p, _)| *p == page).unwrap();
+        assert_eq!(geometry.vertices.len(), 4);
+        assert_eq!(geometry.vertices[0].color, [1.0, 1.0, 1.0, 1.0]);
+
+        // images survive the atlas being cleared
+        fonts.clear_glyphs();
+        assert!(fonts.image("fire").is_some());
+        assert!(fonts.remove_image("fire"));
+        assert_eq!(fonts.image("fire"), None);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{Point, Rect, Scalar};
+
+/// A 2D affine transform, mapping (x, y) to (a * x + c * y + tx, b * x + d * y + ty)
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform2D {
+    pub a: Scalar,
+    pub b: Scalar,
+    pub c: Scalar,
+    pub d: Scalar,
+    pub tx: Scalar,
+    pub ty: Scalar,
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform2D {
+    pub fn identity() -> Self {
+        Self {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            tx: 0.0,
+            ty: 0.0,
+        }
+    }
+
+    pub fn translation(x: Scalar, y: Scalar) -> Self {
+        Self {
+            tx: x,
+            ty: y,
+            ..Self::identity()
+        }
+    }
+
+    pub fn scale(sx: Scalar, sy: Scalar) -> Self {
+        Self {
+            a: sx,
+            d: sy,
+            ..Self::identity()
+        }
+    }
+
+    /// Rotation by `radians`, clockwise on screen since y points down
+    ///
+    pub fn rotation(radians: Scalar) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self {
+            a: cos,
+            b: sin,
+            c: -sin,
+            d: cos,
+            tx: 0.0,
+            ty: 0.0,
+        }
+    }
+
+    /// The transform that applies `self` and then `other`
+    ///
+    pub fn then(&self, other: &Self) -> Self {
+        Self {
+            a: other.a * self.a + other.c * self.b,
+            b: other.b * self.a + other.d * self.b,
+            c: other.a * self.c + other.c * self.d,
+            d: other.b * self.c + other.d * self.d,
+            tx: other.a * self.tx + other.c * self.ty + other.tx,
+            ty: other.b * self.tx + other.d * self.ty + other.ty,
+        }
+    }
+
+    /// Returns `None` if the transform collapses the plane onto a line or point
+    ///
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.a * self.d - self.b * self.c;
+        if det.abs() <= Scalar::EPSILON {
+            return None;
+        }
+        let a = self.d / det;
+        let b = -self.b / det;
+        let c = -self.c / det;
+        let d = self.a / det;
+        Some(Self {
+            a,
+            b,
+            c,
+            d,
+            tx: -(a * self.tx + c * self.ty),
+            ty: -(b * self.tx + d * self.ty),
+        })
+    }
+
+    pub fn transform_point(&self, p: Point) -> Point {
+        Point::from_xy(
+            self.a * p.x + self.c * p.y + self.tx,
+            self.b * p.x + self.d * p.y + self.ty,
+        )
+    }
+
+    /// Transform a direction, ignoring translation
+    ///
+    pub fn transform_vector(&self, v: Point) -> Point {
+        Point::from_xy(self.a * v.x + self.c * v.y, self.b * v.x + self.d * v.y)
+    }
+
+    /// The axis aligned bounds of the transformed rect
+    ///
+    pub fn transform_rect(&self, rect: &Rect) -> Rect {
+        let corners = [
+            self.transform_point(rect.point),
+            self.transform_point(Point::from_xy(rect.right(), rect.top())),
+            self.transform_point(rect.bottom_right()),
+            self.transform_point(Point::from_xy(rect.left(), rect.bottom())),
+        ];
+        let min = corners[1..].iter().fold(corners[0], |m, p| {
+            Point::from_xy(Scalar::min(m.x, p.x), Scalar::min(m.y, p.y))
+        });
+        let max = corners[1..].iter().fold(corners[0], |m, p| {
+            Point::from_xy(Scalar::max(m.x, p.x), Scalar::max(m.y, p.y))
+        });
+        Rect::from_corners(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Point, Rect, Transform2D};
+    use std::f32::consts::FRAC_PI_2;
+
+    fn close(a: Point, b: Point) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn transform_compose() {
+        let t = Transform2D::scale(2.0, 3.0).then(&Transform2D::translation(10.0, 20.0));
+        assert_eq!(
+            t.transform_point(Point::from_xy(1.0, 1.0)),
+            Point::from_xy(12.0, 23.0)
+        );
+        assert_eq!(
+            t.transform_vector(Point::from_xy(1.0, 1.0)),
+            Point::from_xy(2.0, 3.0)
+        );
+
+        let r = Transform2D::rotation(FRAC_PI_2);
+        assert!(close(
+            r.transform_point(Point::from_xy(1.0, 0.0)),
+            Point::from_xy(0.0, 1.0)
+        ));
+    }
+
+    #[test]
+    fn transform_inverse() {
+        let t = Transform2D::rotation(0.3)
+            .then(&Transform2D::scale(2.0, 0.5))
+            .then(&Transform2D::translation(-4.0, 7.0));
+        let p = Point::from_xy(3.0, -2.0);
+        let inverse = t.inverse().unwrap();
+        assert!(close(inverse.transform_point(t.transform_point(p)), p));
+
+        assert!(Transform2D::scale(0.0, 1.0).inverse().is_none());
+    }
+
+    #[test]
+    fn transform_rect_bounds() {
+        let r = Rect::from_values(0.0, 0.0, 10.0, 20.0);
+        assert_eq!(
+            Transform2D::scale(2.0, 2.0)
+                .then(&Transform2D::translation(1.0, 1.0))
+                .transform_rect(&r),
+            Rect::from_values(1.0, 1.0, 20.0, 40.0)
+        );
+
+        let rotated = Transform2D::rotation(FRAC_PI_2).transform_rect(&r);
+        assert!(close(rotated.point, Point::from_xy(-20.0, 0.0)));
+        assert!((rotated.width() - 20.0).abs() < 1e-5);
+        assert!((rotated.height() - 10.0).abs() < 1e-5);
+    }
+}
@@ -1,10 +1,30 @@
-use crate::{CluiColor, Rect, Scalar, Size};
+use crate::{CluiColor, Corner, Length, LengthContext, Point, Rect, Scalar, Size};
+use std::ops::{Index, IndexMut};
 
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MarginDef {
     Auto,
-    Value(Scalar),
+    Value(Length),
 }
 
+impl Default for MarginDef {
+    fn default() -> Self {
+        MarginDef::Value(Length::default())
+    }
+}
+
+impl MarginDef {
+    /// The margin in pixels, `None` if it is `Auto`
+    ///
+    pub fn resolve(&self, context: &LengthContext) -> Option<Scalar> {
+        match self {
+            MarginDef::Auto => None,
+            MarginDef::Value(length) => Some(length.resolve(context, context.parent_content.width)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct Margin {
     pub left: MarginDef,
     pub right: MarginDef,
@@ -12,23 +32,128 @@ pub struct Margin {
     pub bottom: MarginDef,
 }
 
-pub struct Padding {
-    pub left: Scalar,
-    pub right: Scalar,
-    pub top: Scalar,
-    pub bottom: Scalar,
+impl Margin {
+    pub fn all(margin: MarginDef) -> Self {
+        Self {
+            left: margin,
+            right: margin,
+            top: margin,
+            bottom: margin,
+        }
+    }
+
+    /// The fixed margins in pixels, with `Auto` treated as zero
+    ///
+    pub fn fixed(&self, context: &LengthContext) -> Padding {
+        let value = |margin: MarginDef| margin.resolve(context).unwrap_or(0.0);
+        Padding {
+            left: value(self.left),
+            right: value(self.right),
+            top: value(self.top),
+            bottom: value(self.bottom),
+        }
+    }
+}
+
+/// A value for each side of a box, in pixels unless given in `Length`
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Padding<T = Scalar> {
+    pub left: T,
+    pub right: T,
+    pub top: T,
+    pub bottom: T,
+}
+
+impl<T: Copy> Padding<T> {
+    pub fn all(padding: impl Into<T>) -> Self {
+        let padding = padding.into();
+        Self {
+            left: padding,
+            right: padding,
+            top: padding,
+            bottom: padding,
+        }
+    }
+}
+
+impl Padding<Length> {
+    /// The padding in pixels. Percentages are of the parent's content width on every side.
+    ///
+    pub fn resolve(&self, context: &LengthContext) -> Padding {
+        let width = context.parent_content.width;
+        Padding {
+            left: self.left.resolve(context, width),
+            right: self.right.resolve(context, width),
+            top: self.top.resolve(context, width),
+            bottom: self.bottom.resolve(context, width),
+        }
+    }
+}
+
+impl Padding {
+    pub fn horizontal(&self) -> Scalar {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> Scalar {
+        self.top + self.bottom
+    }
 }
 
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct BorderMetrics {
-    pub size: Scalar,
+    pub size: Length,
     pub color: CluiColor,
 }
 
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub enum BorderDef {
+    #[default]
     None,
     Solid(BorderMetrics),
+    Dashed(BorderMetrics),
+    Dotted(BorderMetrics),
+    /// Two lines with a gap between them, each a third of the border width
+    Double(BorderMetrics),
+    /// Shaded so the box looks sunken
+    Inset(BorderMetrics),
+    /// Shaded so the box looks raised
+    Outset(BorderMetrics),
+}
+
+impl BorderDef {
+    pub fn metrics(&self) -> Option<&BorderMetrics> {
+        match self {
+            BorderDef::None => None,
+            BorderDef::Solid(metrics)
+            | BorderDef::Dashed(metrics)
+            | BorderDef::Dotted(metrics)
+            | BorderDef::Double(metrics)
+            | BorderDef::Inset(metrics)
+            | BorderDef::Outset(metrics) => Some(metrics),
+        }
+    }
+
+    pub fn metrics_mut(&mut self) -> Option<&mut BorderMetrics> {
+        match self {
+            BorderDef::None => None,
+            BorderDef::Solid(metrics)
+            | BorderDef::Dashed(metrics)
+            | BorderDef::Dotted(metrics)
+            | BorderDef::Double(metrics)
+            | BorderDef::Inset(metrics)
+            | BorderDef::Outset(metrics) => Some(metrics),
+        }
+    }
+
+    pub fn width(&self) -> Length {
+        self.metrics()
+            .map_or(Length::default(), |metrics| metrics.size)
+    }
 }
 
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct Border {
     pub left: BorderDef,
     pub right: BorderDef,
@@ -36,12 +161,425 @@ pub struct Border {
     pub bottom: BorderDef,
 }
 
+impl Border {
+    pub fn all(border: BorderDef) -> Self {
+        Self {
+            left: border,
+            right: border,
+            top: border,
+            bottom: border,
+        }
+    }
+
+    /// The width of each side, as an inset
+    ///
+    pub fn widths(&self) -> Padding<Length> {
+        Padding {
+            left: self.left.width(),
+            right: self.right.width(),
+            top: self.top.width(),
+            bottom: self.bottom.width(),
+        }
+    }
+}
+
+/// Radius of each corner of the border box
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct CornerRadii {
+    pub top_left: Scalar,
+    pub top_right: Scalar,
+    pub bottom_left: Scalar,
+    pub bottom_right: Scalar,
+}
+
+impl CornerRadii {
+    pub fn all(radius: Scalar) -> Self {
+        Self {
+            top_left: radius,
+            top_right: radius,
+            bottom_left: radius,
+            bottom_right: radius,
+        }
+    }
+
+    /// Scale the radii down, as CSS does, so adjacent corners never overlap on a box of `size`
+    ///
+    pub fn clamped_to(&self, size: Size) -> Self {
+        let fit = |length: Scalar, a: Scalar, b: Scalar| {
+            if a + b > length {
+                length / (a + b)
+            } else {
+                1.0
+            }
+        };
+        let f = fit(size.width, self.top_left, self.top_right)
+            .min(fit(size.width, self.bottom_left, self.bottom_right))
+            .min(fit(size.height, self.top_left, self.bottom_left))
+            .min(fit(size.height, self.top_right, self.bottom_right));
+
+        Self {
+            top_left: self.top_left * f,
+            top_right: self.top_right * f,
+            bottom_left: self.bottom_left * f,
+            bottom_right: self.bottom_right * f,
+        }
+    }
+}
+
+impl Index<Corner> for CornerRadii {
+    type Output = Scalar;
+
+    fn index(&self, corner: Corner) -> &Scalar {
+        match corner {
+            Corner::TopLeft => &self.top_left,
+            Corner::TopRight => &self.top_right,
+            Corner::BottomLeft => &self.bottom_left,
+            Corner::BottomRight => &self.bottom_right,
+        }
+    }
+}
+
+impl IndexMut<Corner> for CornerRadii {
+    fn index_mut(&mut self, corner: Corner) -> &mut Scalar {
+        match corner {
+            Corner::TopLeft => &mut self.top_left,
+            Corner::TopRight => &mut self.top_right,
+            Corner::BottomLeft => &mut self.bottom_left,
+            Corner::BottomRight => &mut self.bottom_right,
+        }
+    }
+}
+
+/// The box of a window, with its lengths in any unit. They are resolved when the box is placed:
+/// percentages are of the parent's content width, or of its content height for heights.
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct BoxModel {
     pub margin: Margin,
-    pub padding: Padding,
-    pub content: Size,
+    pub border: Border,
+    pub border_radius: CornerRadii,
+    pub padding: Padding<Length>,
+    /// Size of the content box. An axis left as `None` is sized by layouts to the window's text
+    /// or measure function, and is otherwise empty.
+    pub content_width: Option<Length>,
+    pub content_height: Option<Length>,
+    /// Font size of the window and, unless they set their own, its descendants. `Em` and
+    /// `Percent` are of the parent's font size.
+    pub font_size: Option<Length>,
     pub background_color: CluiColor,
+    /// Not read by the layer, which paints windows in the order of `CluiUiRect::z_index`
     pub z_order: Scalar,
-    //pub rect: Rect,
-    //dirty: bool,
+    /// Size limits for the border box, applied by layouts. Minimums win over maximums.
+    pub min_width: Length,
+    pub max_width: Option<Length>,
+    pub min_height: Length,
+    pub max_height: Option<Length>,
+    /// Width over height of the border box, kept by layouts within the size limits
+    pub aspect_ratio: Option<Scalar>,
+}
+
+/// The nested rects of a resolved box, from the outside in
+///
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct BoxRects {
+    pub margin: Rect,
+    pub border: Rect,
+    pub padding: Rect,
+    pub content: Rect,
+}
+
+impl BoxRects {
+    pub fn snap_to_pixels(&self, scale: Scalar) -> Self {
+        Self {
+            margin: self.margin.snap_to_pixels(scale),
+            border: self.border.snap_to_pixels(scale),
+            padding: self.padding.snap_to_pixels(scale),
+            content: self.content.snap_to_pixels(scale),
+        }
+    }
+}
+
+impl BoxModel {
+    /// The context the box's own lengths are resolved in, given the one its parent places it in
+    ///
+    pub fn length_context(&self, parent: &LengthContext) -> LengthContext {
+        LengthContext {
+            font_size: self.font_size.map_or(parent.font_size, |size| {
+                size.resolve(parent, parent.font_size)
+            }),
+            ..*parent
+        }
+    }
+
+    /// The size of the content box in pixels, empty on an axis without a size
+    ///
+    pub fn content_size(&self, context: &LengthContext) -> Size {
+        let parent = context.parent_content;
+        Size::from_wh(
+            self.content_width
+                .map_or(0.0, |width| width.resolve(context, parent.width)),
+            self.content_height
+                .map_or(0.0, |height| height.resolve(context, parent.height)),
+        )
+    }
+
+    /// The space the padding and border take around the content
+    ///
+    pub fn frame_size(&self, context: &LengthContext) -> Size {
+        let padding = self.padding.resolve(context);
+        let border = self.border.widths().resolve(context);
+        Size::from_wh(
+            padding.horizontal() + border.horizontal(),
+            padding.vertical() + border.vertical(),
+        )
+    }
+
+    /// The size of the border box when it wraps the content size
+    ///
+    pub fn border_size(&self, context: &LengthContext) -> Size {
+        let frame = self.frame_size(context);
+        self.content_size(context).grow(frame.width, frame.height)
+    }
+
+    /// The border size plus fixed margins, the space the box asks for from a layout
+    ///
+    pub fn outer_size(&self, context: &LengthContext) -> Size {
+        let margin = self.margin.fixed(context);
+        self.border_size(context)
+            .grow(margin.horizontal(), margin.vertical())
+    }
+
+    /// Place the box inside `available`. On an axis with an `Auto` margin the border box takes
+    /// its size from the content size and the auto margins share the remaining space, so two
+    /// auto margins center the box. Otherwise the border box fills whatever the margins leave.
+    ///
+    pub fn resolve(&self, available: &Rect, context: &LengthContext) -> BoxRects {
+        let border_size = self.border_size(context);
+        let (x, width) = Self::resolve_axis(
+            available.left(),
+            available.width(),
+            self.margin.left.resolve(context),
+            self.margin.right.resolve(context),
+            border_size.width,
+        );
+        let (y, height) = Self::resolve_axis(
+            available.top(),
+            available.height(),
+            self.margin.top.resolve(context),
+            self.margin.bottom.resolve(context),
+            border_size.height,
+        );
+
+        let border_rect = Rect::from_values(x, y, width, height);
+        let padding_rect = border_rect.inset(&self.border.widths().resolve(context));
+        let content_rect = padding_rect.inset(&self.padding.resolve(context));
+
+        BoxRects {
+            margin: *available,
+            border: border_rect,
+            padding: padding_rect,
+            content: content_rect,
+        }
+    }
+
+    // Returns the start position and size of the border box along one axis, given the margins
+    // in pixels with `None` for `Auto`
+    fn resolve_axis(
+        start: Scalar,
+        available: Scalar,
+        margin_start: Option<Scalar>,
+        margin_end: Option<Scalar>,
+        border_size: Scalar,
+    ) -> (Scalar, Scalar) {
+        let fixed = margin_start.unwrap_or(0.0) + margin_end.unwrap_or(0.0);
+
+        match (margin_start, margin_end) {
+            (None, None) => {
+                let free = Scalar::max(available - fixed - border_size, 0.0);
+                (start + free / 2.0, border_size)
+            }
+            (None, Some(_)) => {
+                let free = Scalar::max(available - fixed - border_size, 0.0);
+                (start + free, border_size)
+            }
+            (Some(start_margin), None) => (start + start_margin, border_size),
+            (Some(start_margin), Some(_)) => {
+                (start + start_margin, Scalar::max(available - fixed, 0.0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ui_box::*;
+    use crate::{CluiColor, Corner, Length, LengthContext, Rect, Size};
+
+    #[test]
+    fn resolve_fills_available() {
+        let model = BoxModel {
+            margin: Margin::all(MarginDef::Value(Length::Px(5.0))),
+            border: Border::all(BorderDef::Solid(BorderMetrics {
+                size: Length::Px(2.0),
+                color: CluiColor::default(),
+            })),
+            padding: Padding::all(3.0),
+            ..Default::default()
+        };
+
+        let rects = model.resolve(
+            &Rect::from_values(10.0, 20.0, 100.0, 50.0),
+            &LengthContext::default(),
+        );
+        assert_eq!(rects.margin, Rect::from_values(10.0, 20.0, 100.0, 50.0));
+        assert_eq!(rects.border, Rect::from_values(15.0, 25.0, 90.0, 40.0));
+        assert_eq!(rects.padding, Rect::from_values(17.0, 27.0, 86.0, 36.0));
+        assert_eq!(rects.content, Rect::from_values(20.0, 30.0, 80.0, 30.0));
+    }
+
+    #[test]
+    fn resolve_auto_margins_center() {
+        let model = BoxModel {
+            margin: Margin::all(MarginDef::Auto),
+            padding: Padding::all(5.0),
+            content_width: Some(Length::Px(40.0)),
+            content_height: Some(Length::Px(10.0)),
+            ..Default::default()
+        };
+
+        let rects = model.resolve(
+            &Rect::from_values(0.0, 0.0, 100.0, 100.0),
+            &LengthContext::default(),
+        );
+        assert_eq!(rects.border, Rect::from_values(25.0, 40.0, 50.0, 20.0));
+        assert_eq!(rects.content, Rect::from_values(30.0, 45.0, 40.0, 10.0));
+    }
+
+    #[test]
+    fn resolve_single_auto_margin() {
+        let model = BoxModel {
+            margin: Margin {
+                left: MarginDef::Auto,
+                right: MarginDef::Value(Length::Px(10.0)),
+                top: MarginDef::Value(Length::Px(10.0)),
+                bottom: MarginDef::Auto,
+            },
+            content_width: Some(Length::Px(20.0)),
+            content_height: Some(Length::Px(20.0)),
+            ..Default::default()
+        };
+
+        // pushed against the right and top edges
+        let rects = model.resolve(
+            &Rect::from_values(0.0, 0.0, 100.0, 100.0),
+            &LengthContext::default(),
+        );
+        assert_eq!(rects.border, Rect::from_values(70.0, 10.0, 20.0, 20.0));
+    }
+
+    #[test]
+    fn intrinsic_sizes() {
+        let model = BoxModel {
+            margin: Margin {
+                left: MarginDef::Auto,
+                right: MarginDef::Value(Length::Px(4.0)),
+                top: MarginDef::Value(Length::Px(1.0)),
+                bottom: MarginDef::Value(Length::Px(2.0)),
+            },
+            border: Border::all(BorderDef::Solid(BorderMetrics {
+                size: Length::Px(1.0),
+                color: CluiColor::default(),
+            })),
+            padding: Padding::all(5.0),
+            content_width: Some(Length::Px(20.0)),
+            content_height: Some(Length::Px(10.0)),
+            ..Default::default()
+        };
+        let context = LengthContext::default();
+        assert_eq!(model.border_size(&context), Size::from_wh(32.0, 22.0));
+        assert_eq!(model.outer_size(&context), Size::from_wh(36.0, 25.0));
+    }
+
+    #[test]
+    fn lengths_resolved_in_context() {
+        let model = BoxModel {
+            margin: Margin {
+                left: MarginDef::Auto,
+                ..Margin::all(MarginDef::Value(Length::Percent(10.0)))
+            },
+            border: Border {
+                left: BorderDef::Solid(BorderMetrics {
+                    size: Length::Px(2.0),
+                    color: CluiColor::default(),
+                }),
+                ..Default::default()
+            },
+            padding: Padding::all(Length::Em(0.5)),
+            content_width: Some(Length::Vw(25.0)),
+            content_height: Some(Length::Percent(50.0)),
+            font_size: Some(Length::Em(2.0)),
+            ..Default::default()
+        };
+        let parent = LengthContext {
+            font_size: 10.0,
+            root_font_size: 16.0,
+            viewport: Size::from_wh(800.0, 600.0),
+            parent_content: Size::from_wh(400.0, 300.0),
+        };
+        let context = model.length_context(&parent);
+        assert_eq!(context.font_size, 20.0);
+        assert_eq!(
+            model.margin.fixed(&context),
+            Padding {
+                left: 0.0,
+                ..Padding::all(40.0)
+            }
+        );
+        assert_eq!(model.padding.resolve(&context), Padding::all(10.0));
+        assert_eq!(model.content_size(&context), Size::from_wh(200.0, 150.0));
+        assert_eq!(model.border_size(&context), Size::from_wh(222.0, 170.0));
+
+        // the auto margin takes what the fixed margin and border box leave
+        let rects = model.resolve(&Rect::from_values(0.0, 0.0, 400.0, 300.0), &context);
+        assert_eq!(rects.border, Rect::from_values(138.0, 40.0, 222.0, 220.0));
+
+        // a font size in percent is of the parent's
+        let model = BoxModel {
+            font_size: Some(Length::Percent(50.0)),
+            ..Default::default()
+        };
+        assert_eq!(model.length_context(&parent).font_size, 5.0);
+        assert_eq!(BoxModel::default().length_context(&parent), parent);
+    }
+
+    #[test]
+    fn corner_radii_clamped() {
+        let mut radii = CornerRadii::all(10.0);
+        radii[Corner::TopLeft] = 30.0;
+        assert_eq!(radii[Corner::TopLeft], 30.0);
+
+        // 30 + 10 along the top of a 20 wide box halves every radius
+        let clamped = radii.clamped_to(Size::from_wh(20.0, 100.0));
+        assert_eq!(clamped.top_left, 15.0);
+        assert_eq!(clamped.bottom_right, 5.0);
+
+        assert_eq!(radii.clamped_to(Size::from_wh(100.0, 100.0)), radii);
+    }
+
+    #[test]
+    fn resolve_never_negative() {
+        let model = BoxModel {
+            margin: Margin::all(MarginDef::Value(Length::Px(30.0))),
+            padding: Padding::all(10.0),
+            ..Default::default()
+        };
+
+        let rects = model.resolve(
+            &Rect::from_values(0.0, 0.0, 50.0, 50.0),
+            &LengthContext::default(),
+        );
+        assert_eq!(rects.border.size, Size::from_wh(0.0, 0.0));
+        assert_eq!(rects.content.size, Size::from_wh(0.0, 0.0));
+    }
 }
@@ -0,0 +1,206 @@
+use crate::LayoutItem;
+use clui_base::{Padding, Point, Rect, Scalar, Size};
+
+/// Placement of a child against anchors in its parent, in the style of Unity's RectTransform.
+/// Anchors and the pivot are fractions, with (0, 0) the top left of the parent's content box
+/// and (1, 1) the bottom right.
+///
+/// The anchors mark out a rect in the parent, which `size_delta` grows. The pivot is a point in
+/// the child that is placed `position` away from the same fraction of the anchor rect.
+///
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AnchorItem {
+    pub anchor_min: Point,
+    pub anchor_max: Point,
+    pub pivot: Point,
+    pub position: Point,
+    pub size_delta: Size,
+}
+
+impl Default for AnchorItem {
+    /// Fill the parent
+    ///
+    fn default() -> Self {
+        Self::stretch(Padding::default())
+    }
+}
+
+impl AnchorItem {
+    /// A fixed size child whose `pivot` sits `offset` away from the `anchor` point of the parent
+    ///
+    pub fn point(anchor: Point, pivot: Point, offset: Point, size: Size) -> Self {
+        Self {
+            anchor_min: anchor,
+            anchor_max: anchor,
+            pivot,
+            position: offset,
+            size_delta: size,
+        }
+    }
+
+    /// Stretch across the parent, inset by `margin` on each side
+    ///
+    pub fn stretch(margin: Padding) -> Self {
+        Self {
+            anchor_min: Point::from_xy(0.0, 0.0),
+            anchor_max: Point::from_xy(1.0, 1.0),
+            pivot: Point::from_xy(0.0, 0.0),
+            position: Point::from_xy(margin.left, margin.top),
+            size_delta: Size::from_wh(-margin.horizontal(), -margin.vertical()),
+        }
+    }
+
+    /// The margin box of the child, relative to the parent's content box
+    ///
+    pub fn resolve(&self, parent: Size) -> Rect {
+        let (x, width) = Self::resolve_axis(
+            parent.width,
+            self.anchor_min.x,
+            self.anchor_max.x,
+            self.pivot.x,
+            self.position.x,
+            self.size_delta.width,
+        );
+        let (y, height) = Self::resolve_axis(
+            parent.height,
+            self.anchor_min.y,
+            self.anchor_max.y,
+            self.pivot.y,
+            self.position.y,
+            self.size_delta.height,
+        );
+        Rect::from_values(x, y, width, height)
+    }
+
+    fn resolve_axis(
+        parent: Scalar,
+        anchor_min: Scalar,
+        anchor_max: Scalar,
+        pivot: Scalar,
+        position: Scalar,
+        size_delta: Scalar,
+    ) -> (Scalar, Scalar) {
+        let min = parent * anchor_min;
+        let max = parent * anchor_max;
+        let size = Scalar::max(max - min + size_delta, 0.0);
+        let reference = min + (max - min) * pivot;
+        (reference + position - size * pivot, size)
+    }
+}
+
+// Items that can not fill their anchored rect because of their size limits keep their pivot
+// in the same place
+pub(crate) fn layout(content: Size, items: &[LayoutItem]) -> Vec<Rect> {
+    items
+        .iter()
+        .map(|item| {
+            let anchor = &item.style.anchor;
+            let rect = anchor.resolve(content);
+            let margin = Size::from_wh(item.margin.horizontal(), item.margin.vertical());
+            let size = item.constraints.fit(rect.size - margin) + margin;
+            Rect::from_values(
+                rect.left() + (rect.width() - size.width) * anchor.pivot.x,
+                rect.top() + (rect.height() - size.height) * anchor.pivot.y,
+                size.width,
+                size.height,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::anchor::*;
+    use crate::constraints::Constraints;
+    use crate::{ItemStyle, LayoutItem};
+
+    #[test]
+    fn anchored_to_corner() {
+        // top right, 20 in from each edge
+        let item = AnchorItem::point(
+            Point::from_xy(1.0, 0.0),
+            Point::from_xy(1.0, 0.0),
+            Point::from_xy(-20.0, 20.0),
+            Size::from_wh(100.0, 30.0),
+        );
+        assert_eq!(
+            item.resolve(Size::from_wh(800.0, 600.0)),
+            Rect::from_values(680.0, 20.0, 100.0, 30.0)
+        );
+        assert_eq!(
+            item.resolve(Size::from_wh(1920.0, 1080.0)),
+            Rect::from_values(1800.0, 20.0, 100.0, 30.0)
+        );
+
+        // centered on the bottom edge
+        let item = AnchorItem::point(
+            Point::from_xy(0.5, 1.0),
+            Point::from_xy(0.5, 1.0),
+            Point::default(),
+            Size::from_wh(200.0, 40.0),
+        );
+        assert_eq!(
+            item.resolve(Size::from_wh(800.0, 600.0)),
+            Rect::from_values(300.0, 560.0, 200.0, 40.0)
+        );
+    }
+
+    #[test]
+    fn stretched_between_anchors() {
+        let item = AnchorItem::stretch(Padding {
+            left: 10.0,
+            right: 20.0,
+            top: 5.0,
+            bottom: 5.0,
+        });
+        assert_eq!(
+            item.resolve(Size::from_wh(100.0, 50.0)),
+            Rect::from_values(10.0, 5.0, 70.0, 40.0)
+        );
+        assert_eq!(
+            AnchorItem::default().resolve(Size::from_wh(100.0, 50.0)),
+            Rect::from_values(0.0, 0.0, 100.0, 50.0)
+        );
+
+        // a health bar across the bottom quarter, centered pivot, moved up by 4
+        let item = AnchorItem {
+            anchor_min: Point::from_xy(0.25, 0.75),
+            anchor_max: Point::from_xy(0.75, 1.0),
+            pivot: Point::from_xy(0.5, 0.5),
+            position: Point::from_xy(0.0, -4.0),
+            size_delta: Size::from_wh(-10.0, 0.0),
+        };
+        assert_eq!(
+            item.resolve(Size::from_wh(400.0, 200.0)),
+            Rect::from_values(105.0, 146.0, 190.0, 50.0)
+        );
+    }
+
+    #[test]
+    fn limits_keep_the_pivot() {
+        // a square minimap in the top right corner, a quarter of the screen height
+        let style = ItemStyle {
+            anchor: AnchorItem {
+                anchor_min: Point::from_xy(1.0, 0.0),
+                anchor_max: Point::from_xy(1.0, 0.25),
+                pivot: Point::from_xy(1.0, 0.0),
+                position: Point::from_xy(-10.0, 10.0),
+                size_delta: Size::from_wh(400.0, 0.0),
+            },
+            ..Default::default()
+        };
+        let item = LayoutItem {
+            margin: Padding::default(),
+            size: Size::default(),
+            constraints: Constraints {
+                aspect_ratio: Some(1.0),
+                ..Default::default()
+            },
+            style: &style,
+        };
+        assert_eq!(
+            layout(Size::from_wh(800.0, 600.0), &[item])[0],
+            Rect::from_values(640.0, 10.0, 150.0, 150.0)
+        );
+    }
+}
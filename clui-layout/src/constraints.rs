@@ -0,0 +1,170 @@
+use clui_base::{BoxModel, Length, LengthContext, Scalar, Size};
+
+// Size limits of an item's border box, gathered from its box model with its lengths resolved in
+// the item's context. The border box is never allowed to be smaller than its padding and border.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct Constraints {
+    pub min: Size,
+    pub max: Size,
+    // width over height
+    pub aspect_ratio: Option<Scalar>,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self {
+            min: Size::default(),
+            max: Size::from_wh(Scalar::INFINITY, Scalar::INFINITY),
+            aspect_ratio: None,
+        }
+    }
+}
+
+impl Constraints {
+    pub fn from_box_model(model: &BoxModel, context: &LengthContext) -> Self {
+        let chrome = model.frame_size(context);
+        let parent = context.parent_content;
+        let width = |length: Length| length.resolve(context, parent.width);
+        let height = |length: Length| length.resolve(context, parent.height);
+        Self {
+            min: Size::from_wh(
+                Scalar::max(width(model.min_width), chrome.width),
+                Scalar::max(height(model.min_height), chrome.height),
+            ),
+            max: Size::from_wh(
+                model.max_width.map_or(Scalar::INFINITY, width),
+                model.max_height.map_or(Scalar::INFINITY, height),
+            ),
+            aspect_ratio: model.aspect_ratio.filter(|r| *r > 0.0 && r.is_finite()),
+        }
+    }
+
+    pub fn clamp_width(&self, width: Scalar) -> Scalar {
+        Scalar::max(Scalar::min(width, self.max.width), self.min.width)
+    }
+
+    pub fn clamp_height(&self, height: Scalar) -> Scalar {
+        Scalar::max(Scalar::min(height, self.max.height), self.min.height)
+    }
+
+    pub fn clamp(&self, size: Size) -> Size {
+        Size::from_wh(self.clamp_width(size.width), self.clamp_height(size.height))
+    }
+
+    // The size an item asks for: its intrinsic size within the limits, with the height taken
+    // from the width when there is an aspect ratio
+    pub fn intrinsic(&self, size: Size) -> Size {
+        let size = self.clamp(size);
+        match self.aspect_ratio {
+            Some(ratio) if size.width > 0.0 => {
+                size.with_height(self.clamp_height(size.width / ratio))
+            }
+            Some(ratio) => size.with_width(self.clamp_width(size.height * ratio)),
+            None => size,
+        }
+    }
+
+    // The largest size within `available` that keeps the aspect ratio, if there is one. The
+    // limits win when they can not be met inside `available`.
+    pub fn fit(&self, available: Size) -> Size {
+        let size = self.clamp(available);
+        match self.aspect_ratio {
+            Some(ratio) if size.width / ratio <= size.height => {
+                self.clamp(size.with_height(size.width / ratio))
+            }
+            Some(ratio) => self.clamp(size.with_width(size.height * ratio)),
+            None => size,
+        }
+    }
+
+    // Why no size can meet every constraint, if that is the case
+    pub fn conflict(&self) -> Option<String> {
+        if self.min.width > self.max.width {
+            return Some(format!(
+                "minimum width {} is larger than max_width {}",
+                self.min.width, self.max.width
+            ));
+        }
+        if self.min.height > self.max.height {
+            return Some(format!(
+                "minimum height {} is larger than max_height {}",
+                self.min.height, self.max.height
+            ));
+        }
+        let ratio = self.aspect_ratio?;
+        let low = Scalar::max(self.min.width, self.min.height * ratio);
+        let high = Scalar::min(self.max.width, self.max.height * ratio);
+        if low > high {
+            return Some(format!(
+                "aspect ratio {} can not be kept within its size limits",
+                ratio
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constraints::*;
+    use clui_base::Padding;
+
+    #[test]
+    fn limits_from_box_model() {
+        let model = BoxModel {
+            padding: Padding::all(10.0),
+            min_width: Length::Px(5.0),
+            max_width: Some(Length::Percent(50.0)),
+            min_height: Length::Em(2.0),
+            ..Default::default()
+        };
+        let context = LengthContext {
+            font_size: 15.0,
+            parent_content: Size::from_wh(200.0, 100.0),
+            ..Default::default()
+        };
+        let constraints = Constraints::from_box_model(&model, &context);
+        assert_eq!(constraints.min, Size::from_wh(20.0, 30.0));
+        assert_eq!(constraints.max.width, 100.0);
+        assert_eq!(
+            constraints.clamp(Size::from_wh(0.0, 500.0)),
+            Size::from_wh(20.0, 500.0)
+        );
+        assert!(constraints.conflict().is_none());
+
+        let tight = BoxModel {
+            max_width: Some(Length::Px(15.0)),
+            ..model
+        };
+        assert!(Constraints::from_box_model(&tight, &context)
+            .conflict()
+            .is_some());
+    }
+
+    #[test]
+    fn aspect_ratio_fit() {
+        let constraints = Constraints {
+            aspect_ratio: Some(2.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            constraints.fit(Size::from_wh(100.0, 100.0)),
+            Size::from_wh(100.0, 50.0)
+        );
+        assert_eq!(
+            constraints.fit(Size::from_wh(100.0, 20.0)),
+            Size::from_wh(40.0, 20.0)
+        );
+        assert_eq!(
+            constraints.intrinsic(Size::from_wh(30.0, 0.0)),
+            Size::from_wh(30.0, 15.0)
+        );
+
+        let impossible = Constraints {
+            min: Size::from_wh(100.0, 0.0),
+            max: Size::from_wh(Scalar::INFINITY, 10.0),
+            aspect_ratio: Some(1.0),
+        };
+        assert!(impossible.conflict().is_some());
+    }
+}
@@ -0,0 +1,617 @@
+use crate::LayoutItem;
+use clui_base::{Rect, Scalar, Size};
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum FlexDirection {
+    #[default]
+    Row,
+    RowReverse,
+    Column,
+    ColumnReverse,
+}
+
+impl FlexDirection {
+    fn is_row(&self) -> bool {
+        matches!(self, FlexDirection::Row | FlexDirection::RowReverse)
+    }
+
+    fn is_reverse(&self) -> bool {
+        matches!(
+            self,
+            FlexDirection::RowReverse | FlexDirection::ColumnReverse
+        )
+    }
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum FlexWrap {
+    #[default]
+    NoWrap,
+    Wrap,
+    /// Wrap, stacking lines from the cross end
+    WrapReverse,
+}
+
+/// Distribution of free space along the main axis
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum JustifyContent {
+    #[default]
+    FlexStart,
+    FlexEnd,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+/// Placement of an item across its line
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum AlignItems {
+    FlexStart,
+    FlexEnd,
+    Center,
+    #[default]
+    Stretch,
+}
+
+/// Distribution of lines along the cross axis when wrapping
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum AlignContent {
+    FlexStart,
+    FlexEnd,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+    #[default]
+    Stretch,
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct FlexContainer {
+    pub direction: FlexDirection,
+    pub wrap: FlexWrap,
+    pub justify_content: JustifyContent,
+    pub align_items: AlignItems,
+    pub align_content: AlignContent,
+    /// Space between rows, or between lines of a wrapping row
+    pub row_gap: Scalar,
+    /// Space between columns, or between lines of a wrapping column
+    pub column_gap: Scalar,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FlexItem {
+    pub grow: Scalar,
+    pub shrink: Scalar,
+    /// Main size of the border box before growing or shrinking. `None` uses the intrinsic size.
+    pub basis: Option<Scalar>,
+    /// Overrides the container's `align_items`
+    pub align_self: Option<AlignItems>,
+}
+
+impl Default for FlexItem {
+    fn default() -> Self {
+        Self {
+            grow: 0.0,
+            shrink: 1.0,
+            basis: None,
+            align_self: None,
+        }
+    }
+}
+
+// An item's sizes along the main and cross axes
+struct Item {
+    margin_main: (Scalar, Scalar),
+    margin_cross: (Scalar, Scalar),
+    base: Scalar,
+    main: Scalar,
+    cross: Scalar,
+    min: (Scalar, Scalar),
+    max: (Scalar, Scalar),
+    // cross size per unit of main size, from the aspect ratio
+    cross_per_main: Option<Scalar>,
+    grow: Scalar,
+    shrink: Scalar,
+    align: AlignItems,
+}
+
+impl Item {
+    fn clamp_main(&self, main: Scalar) -> Scalar {
+        Scalar::max(Scalar::min(main, self.max.0), self.min.0)
+    }
+
+    fn clamp_cross(&self, cross: Scalar) -> Scalar {
+        Scalar::max(Scalar::min(cross, self.max.1), self.min.1)
+    }
+
+    fn margin_main_total(&self) -> Scalar {
+        self.margin_main.0 + self.margin_main.1
+    }
+
+    fn margin_cross_total(&self) -> Scalar {
+        self.margin_cross.0 + self.margin_cross.1
+    }
+}
+
+/// Returns the margin box of each item, relative to the container's content box
+///
+pub(crate) fn layout(container: &FlexContainer, content: Size, items: &[LayoutItem]) -> Vec<Rect> {
+    let row = container.direction.is_row();
+    let (avail_main, avail_cross) = axes(row, content.width, content.height);
+    let (main_gap, cross_gap) = if row {
+        (container.column_gap, container.row_gap)
+    } else {
+        (container.row_gap, container.column_gap)
+    };
+
+    let mut flex_items: Vec<Item> = items
+        .iter()
+        .map(|item| {
+            let style = &item.style.flex;
+            let (main, cross) = axes(row, item.size.width, item.size.height);
+            let (margin_main, margin_cross) = if row {
+                (
+                    (item.margin.left, item.margin.right),
+                    (item.margin.top, item.margin.bottom),
+                )
+            } else {
+                (
+                    (item.margin.top, item.margin.bottom),
+                    (item.margin.left, item.margin.right),
+                )
+            };
+            let c = &item.constraints;
+            let base = Scalar::max(style.basis.unwrap_or(main), 0.0);
+            let mut item = Item {
+                margin_main,
+                margin_cross,
+                base,
+                main: base,
+                cross,
+                min: axes(row, c.min.width, c.min.height),
+                max: axes(row, c.max.width, c.max.height),
+                cross_per_main: c.aspect_ratio.map(|r| if row { 1.0 / r } else { r }),
+                grow: style.grow,
+                shrink: style.shrink,
+                align: style.align_self.unwrap_or(container.align_items),
+            };
+            // cross limits carry over to the main axis through the aspect ratio
+            if let Some(k) = item.cross_per_main {
+                item.min.0 = Scalar::max(item.min.0, item.min.1 / k);
+                item.max.0 = Scalar::max(Scalar::min(item.max.0, item.max.1 / k), item.min.0);
+            }
+            // the hypothetical main size
+            item.main = item.clamp_main(base);
+            item
+        })
+        .collect();
+
+    let lines = break_lines(container.wrap, &flex_items, avail_main, main_gap);
+    for line in &lines {
+        resolve_flexible_lengths(&mut flex_items[line.clone()], avail_main, main_gap);
+    }
+    // an aspect ratio decides the cross size, rather than the item's content or stretching
+    for item in &mut flex_items {
+        if let Some(k) = item.cross_per_main {
+            item.cross = item.clamp_cross(item.main * k);
+            if item.align == AlignItems::Stretch {
+                item.align = AlignItems::FlexStart;
+            }
+        }
+    }
+
+    // cross size of each line
+    let mut line_cross: Vec<Scalar> = if container.wrap == FlexWrap::NoWrap {
+        vec![avail_cross; lines.len()]
+    } else {
+        lines
+            .iter()
+            .map(|line| {
+                flex_items[line.clone()]
+                    .iter()
+                    .map(|item| item.cross + item.margin_cross_total())
+                    .fold(0.0, Scalar::max)
+            })
+            .collect()
+    };
+
+    let free_cross = avail_cross
+        - line_cross.iter().sum::<Scalar>()
+        - cross_gap * (lines.len().max(1) - 1) as Scalar;
+    let (mut cross_pos, cross_between) = if container.wrap == FlexWrap::NoWrap {
+        (0.0, 0.0)
+    } else if container.align_content == AlignContent::Stretch {
+        if free_cross > 0.0 {
+            let extra = free_cross / lines.len().max(1) as Scalar;
+            line_cross.iter_mut().for_each(|c| *c += extra);
+        }
+        (0.0, 0.0)
+    } else {
+        let justify = match container.align_content {
+            AlignContent::FlexStart | AlignContent::Stretch => JustifyContent::FlexStart,
+            AlignContent::FlexEnd => JustifyContent::FlexEnd,
+            AlignContent::Center => JustifyContent::Center,
+            AlignContent::SpaceBetween => JustifyContent::SpaceBetween,
+            AlignContent::SpaceAround => JustifyContent::SpaceAround,
+            AlignContent::SpaceEvenly => JustifyContent::SpaceEvenly,
+        };
+        distribute(justify, free_cross, lines.len())
+    };
+
+    let mut rects = vec![Rect::default(); items.len()];
+    for (line, line_size) in lines.iter().zip(&line_cross) {
+        let line_items = &mut flex_items[line.clone()];
+        let used: Scalar = line_items
+            .iter()
+            .map(|item| item.main + item.margin_main_total())
+            .sum();
+        let free_main = avail_main - used - main_gap * (line_items.len().max(1) - 1) as Scalar;
+        let (mut main_pos, main_between) =
+            distribute(container.justify_content, free_main, line_items.len());
+
+        for (i, item) in line_items.iter_mut().enumerate() {
+            let outer_main = item.main + item.margin_main_total();
+            if item.align == AlignItems::Stretch {
+                item.cross = item.clamp_cross(line_size - item.margin_cross_total());
+            }
+            let outer_cross = item.cross + item.margin_cross_total();
+            let offset_cross = match item.align {
+                AlignItems::FlexStart | AlignItems::Stretch => 0.0,
+                AlignItems::FlexEnd => line_size - outer_cross,
+                AlignItems::Center => (line_size - outer_cross) / 2.0,
+            };
+
+            let mut main_start = main_pos;
+            if container.direction.is_reverse() {
+                main_start = avail_main - main_start - outer_main;
+            }
+            let mut cross_start = cross_pos + offset_cross;
+            if container.wrap == FlexWrap::WrapReverse {
+                cross_start = avail_cross - cross_start - outer_cross;
+            }
+
+            let (x, y) = axes(row, main_start, cross_start);
+            let (width, height) = axes(row, outer_main, outer_cross);
+            rects[line.start + i] = Rect::from_values(x, y, width, height);
+
+            main_pos += outer_main + main_gap + main_between;
+        }
+
+        cross_pos += line_size + cross_gap + cross_between;
+    }
+
+    rects
+}
+
+// Swaps x and y for columns, so the rest of the layout only deals with main and cross
+fn axes(row: bool, x: Scalar, y: Scalar) -> (Scalar, Scalar) {
+    if row {
+        (x, y)
+    } else {
+        (y, x)
+    }
+}
+
+// Split items into lines, each as long as fits in the main axis but never empty
+fn break_lines(
+    wrap: FlexWrap,
+    items: &[Item],
+    avail_main: Scalar,
+    gap: Scalar,
+) -> Vec<std::ops::Range<usize>> {
+    if wrap == FlexWrap::NoWrap || items.is_empty() {
+        let all = 0..items.len();
+        return vec![all];
+    }
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut length = 0.0;
+    for (i, item) in items.iter().enumerate() {
+        let outer = item.main + item.margin_main_total();
+        if i > start && length + gap + outer > avail_main {
+            lines.push(start..i);
+            start = i;
+            length = outer;
+        } else if i == start {
+            length = outer;
+        } else {
+            length += gap + outer;
+        }
+    }
+    lines.push(start..items.len());
+    lines
+}
+
+// Grow or shrink the items of a line to fill it, freezing items as they reach their size limits
+// and sharing what is left among the rest. Items start at their hypothetical main size.
+fn resolve_flexible_lengths(items: &mut [Item], avail_main: Scalar, gap: Scalar) {
+    let gaps = gap * (items.len().max(1) - 1) as Scalar;
+    let hypothetical: Scalar = items
+        .iter()
+        .map(|item| item.main + item.margin_main_total())
+        .sum();
+    let growing = hypothetical + gaps < avail_main;
+
+    // items that can not flex, or are already held past their base size by a limit
+    let mut frozen: Vec<bool> = items
+        .iter()
+        .map(|item| {
+            if growing {
+                item.grow <= 0.0 || item.base > item.main
+            } else {
+                item.shrink <= 0.0 || item.base < item.main
+            }
+        })
+        .collect();
+
+    while frozen.iter().any(|f| !f) {
+        let used: Scalar = items
+            .iter()
+            .zip(&frozen)
+            .map(|(item, f)| if *f { item.main } else { item.base } + item.margin_main_total())
+            .sum();
+        let free = avail_main - gaps - used;
+
+        let unfrozen = || items.iter().zip(&frozen).filter(|(_, f)| !**f);
+        let weight_sum: Scalar = if growing {
+            unfrozen().map(|(item, _)| item.grow).sum()
+        } else {
+            unfrozen().map(|(item, _)| item.shrink * item.base).sum()
+        };
+
+        let mut violation = 0.0;
+        let mut violations = vec![0.0; items.len()];
+        for (i, item) in items.iter_mut().enumerate() {
+            if frozen[i] {
+                continue;
+            }
+            let weight = if growing {
+                item.grow
+            } else {
+                item.shrink * item.base
+            };
+            let target = if weight_sum > 0.0 {
+                item.base + free * weight / weight_sum
+            } else {
+                item.base
+            };
+            let clamped = item.clamp_main(target);
+            violations[i] = clamped - target;
+            violation += clamped - target;
+            item.main = clamped;
+        }
+
+        // freeze everything on a clean pass, otherwise only the items that were clamped
+        for (i, f) in frozen.iter_mut().enumerate() {
+            if *f {
+                continue;
+            }
+            if violation == 0.0
+                || (violation > 0.0 && violations[i] > 0.0)
+                || (violation < 0.0 && violations[i] < 0.0)
+            {
+                *f = true;
+            }
+        }
+    }
+}
+
+// Leading space and extra space between `count` things sharing `free` space. Overflowing
+// content falls back to the start for the space distributing modes.
+pub(crate) fn distribute(justify: JustifyContent, free: Scalar, count: usize) -> (Scalar, Scalar) {
+    let n = count as Scalar;
+    match justify {
+        JustifyContent::FlexStart => (0.0, 0.0),
+        JustifyContent::FlexEnd => (free, 0.0),
+        JustifyContent::Center => (free / 2.0, 0.0),
+        _ if free <= 0.0 || count == 0 => (0.0, 0.0),
+        JustifyContent::SpaceBetween if count == 1 => (0.0, 0.0),
+        JustifyContent::SpaceBetween => (0.0, free / (n - 1.0)),
+        JustifyContent::SpaceAround => (free / n / 2.0, free / n),
+        JustifyContent::SpaceEvenly => (free / (n + 1.0), free / (n + 1.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constraints::Constraints;
+    use crate::flex::*;
+    use crate::{ItemStyle, LayoutItem};
+    use clui_base::Padding;
+
+    fn style(grow: Scalar, shrink: Scalar, basis: Option<Scalar>) -> ItemStyle {
+        ItemStyle {
+            flex: FlexItem {
+                grow,
+                shrink,
+                basis,
+                align_self: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn item(style: &ItemStyle, width: Scalar, height: Scalar) -> LayoutItem<'_> {
+        LayoutItem {
+            margin: Padding::default(),
+            size: Size::from_wh(width, height),
+            constraints: Constraints::default(),
+            style,
+        }
+    }
+
+    #[test]
+    fn grow_shares_free_space() {
+        let a = style(1.0, 1.0, None);
+        let b = style(2.0, 1.0, None);
+        let fixed = ItemStyle::default();
+        let container = FlexContainer {
+            column_gap: 10.0,
+            ..Default::default()
+        };
+        let rects = layout(
+            &container,
+            Size::from_wh(200.0, 50.0),
+            &[
+                item(&a, 10.0, 5.0),
+                item(&b, 10.0, 5.0),
+                item(&fixed, 30.0, 5.0),
+            ],
+        );
+        // 200 - 50 used - 20 of gaps leaves 130 to share 1:2
+        assert_eq!(rects[0].size.height, 50.0);
+        assert!((rects[0].width() - 53.33333).abs() < 1e-3);
+        assert!((rects[1].left() - 63.33333).abs() < 1e-3);
+        assert!((rects[1].width() - 96.66667).abs() < 1e-3);
+        assert_eq!(rects[2], Rect::from_values(170.0, 0.0, 30.0, 50.0));
+    }
+
+    #[test]
+    fn shrink_weighted_by_basis_and_frozen_at_zero() {
+        let a = style(0.0, 1.0, Some(100.0));
+        let b = style(0.0, 1.0, Some(50.0));
+        let rigid = style(0.0, 0.0, Some(50.0));
+        let rects = layout(
+            &FlexContainer::default(),
+            Size::from_wh(140.0, 10.0),
+            &[
+                item(&a, 0.0, 0.0),
+                item(&b, 0.0, 0.0),
+                item(&rigid, 0.0, 0.0),
+            ],
+        );
+        // 60 too much, taken 2:1 from the shrinkable items
+        assert_eq!(rects[0].width(), 60.0);
+        assert_eq!(rects[1].width(), 30.0);
+        assert_eq!(rects[2].width(), 50.0);
+
+        let rects = layout(
+            &FlexContainer::default(),
+            Size::from_wh(20.0, 10.0),
+            &[
+                item(&a, 0.0, 0.0),
+                item(&b, 0.0, 0.0),
+                item(&rigid, 0.0, 0.0),
+            ],
+        );
+        assert!(rects.iter().all(|r| r.width() >= 0.0));
+        assert_eq!(rects[2].width(), 50.0);
+    }
+
+    #[test]
+    fn justify_content_modes() {
+        let s = ItemStyle::default();
+        let items = [item(&s, 20.0, 10.0), item(&s, 20.0, 10.0)];
+        let lefts = |justify_content| {
+            let container = FlexContainer {
+                justify_content,
+                ..Default::default()
+            };
+            layout(&container, Size::from_wh(100.0, 10.0), &items)
+                .iter()
+                .map(|r| r.left())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lefts(JustifyContent::FlexStart), [0.0, 20.0]);
+        assert_eq!(lefts(JustifyContent::FlexEnd), [60.0, 80.0]);
+        assert_eq!(lefts(JustifyContent::Center), [30.0, 50.0]);
+        assert_eq!(lefts(JustifyContent::SpaceBetween), [0.0, 80.0]);
+        assert_eq!(lefts(JustifyContent::SpaceAround), [15.0, 65.0]);
+        assert_eq!(lefts(JustifyContent::SpaceEvenly), [20.0, 60.0]);
+    }
+
+    #[test]
+    fn column_reverse_with_alignment() {
+        let s = ItemStyle::default();
+        let mut centered = ItemStyle::default();
+        centered.flex.align_self = Some(AlignItems::Center);
+        let container = FlexContainer {
+            direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::FlexEnd,
+            ..Default::default()
+        };
+        let mut margined = item(&centered, 10.0, 20.0);
+        margined.margin = Padding::all(5.0);
+        let rects = layout(
+            &container,
+            Size::from_wh(100.0, 100.0),
+            &[item(&s, 10.0, 20.0), margined],
+        );
+        assert_eq!(rects[0], Rect::from_values(90.0, 80.0, 10.0, 20.0));
+        // the margin box is centered, 20 + 10 of margin up from the first item
+        assert_eq!(rects[1], Rect::from_values(40.0, 50.0, 20.0, 30.0));
+    }
+
+    #[test]
+    fn wrapping_lines_and_align_content() {
+        let s = ItemStyle::default();
+        let items = [
+            item(&s, 40.0, 10.0),
+            item(&s, 40.0, 20.0),
+            item(&s, 40.0, 10.0),
+        ];
+        let container = FlexContainer {
+            wrap: FlexWrap::Wrap,
+            align_items: AlignItems::FlexStart,
+            row_gap: 5.0,
+            column_gap: 5.0,
+            ..Default::default()
+        };
+        let rects = layout(&container, Size::from_wh(100.0, 100.0), &items);
+        // 40 + 5 + 40 fits, the third item wraps; stretch shares the spare 65 between the lines
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 40.0, 10.0));
+        assert_eq!(rects[1], Rect::from_values(45.0, 0.0, 40.0, 20.0));
+        assert_eq!(rects[2], Rect::from_values(0.0, 57.5, 40.0, 10.0));
+
+        let container = FlexContainer {
+            wrap: FlexWrap::WrapReverse,
+            align_content: AlignContent::FlexStart,
+            ..container
+        };
+        let rects = layout(&container, Size::from_wh(100.0, 100.0), &items);
+        assert_eq!(rects[1], Rect::from_values(45.0, 80.0, 40.0, 20.0));
+        assert_eq!(rects[0], Rect::from_values(0.0, 90.0, 40.0, 10.0));
+        assert_eq!(rects[2], Rect::from_values(0.0, 65.0, 40.0, 10.0));
+    }
+
+    #[test]
+    fn size_limits_and_aspect_ratio() {
+        let grow = style(1.0, 1.0, None);
+        let mut capped = item(&grow, 0.0, 0.0);
+        capped.constraints.max = Size::from_wh(30.0, Scalar::INFINITY);
+        let mut square = item(&grow, 0.0, 0.0);
+        square.constraints.aspect_ratio = Some(1.0);
+        square.constraints.max.height = 50.0;
+        let mut tall = item(&grow, 0.0, 0.0);
+        tall.constraints.min.height = 80.0;
+        tall.constraints.max.height = 90.0;
+
+        let rects = layout(
+            &FlexContainer::default(),
+            Size::from_wh(200.0, 100.0),
+            &[capped, square, tall],
+        );
+        // the capped item freezes at 30, the square one at 50 through its height limit, and
+        // the last item takes the rest
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 30.0, 100.0));
+        assert_eq!(rects[1], Rect::from_values(30.0, 0.0, 50.0, 50.0));
+        assert_eq!(rects[2], Rect::from_values(80.0, 0.0, 120.0, 90.0));
+
+        let shrink = style(0.0, 1.0, Some(100.0));
+        let mut floor = item(&shrink, 0.0, 0.0);
+        floor.constraints.min.width = 90.0;
+        let rects = layout(
+            &FlexContainer::default(),
+            Size::from_wh(150.0, 10.0),
+            &[floor, item(&shrink, 0.0, 0.0)],
+        );
+        assert_eq!(rects[0].width(), 90.0);
+        assert_eq!(rects[1].width(), 60.0);
+    }
+}
@@ -0,0 +1,637 @@
+use crate::LayoutItem;
+use clui_base::{Rect, Scalar, Size};
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum TrackSize {
+    Fixed(Scalar),
+    /// A share of the space left after fixed and auto tracks
+    Fr(Scalar),
+    /// As large as the largest item in the track. Auto tracks also share any leftover space
+    /// when there are no `Fr` tracks.
+    #[default]
+    Auto,
+}
+
+/// The direction auto-placed items fill the grid in
+///
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum GridAutoFlow {
+    /// Fill each row, adding rows as needed
+    #[default]
+    Row,
+    /// Fill each column, adding columns as needed
+    Column,
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct GridContainer {
+    pub columns: Vec<TrackSize>,
+    pub rows: Vec<TrackSize>,
+    /// Size of tracks added past the explicit ones
+    pub auto_tracks: TrackSize,
+    /// Named areas, one string per row with a space separated name for each cell, as in
+    /// `grid-template-areas`. A `.` leaves the cell unnamed.
+    pub areas: Vec<String>,
+    pub auto_flow: GridAutoFlow,
+    pub row_gap: Scalar,
+    pub column_gap: Scalar,
+}
+
+/// Where an item sits in the grid. Tracks are counted from zero, and an unset row or column is
+/// chosen by auto-placement.
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct GridItem {
+    pub column: Option<usize>,
+    pub row: Option<usize>,
+    pub column_span: usize,
+    pub row_span: usize,
+    /// A named area from the container, overriding the row, column and spans. Unknown names are
+    /// auto-placed.
+    pub area: Option<String>,
+}
+
+impl Default for GridItem {
+    fn default() -> Self {
+        Self {
+            column: None,
+            row: None,
+            column_span: 1,
+            row_span: 1,
+            area: None,
+        }
+    }
+}
+
+impl GridItem {
+    pub fn at(column: usize, row: usize) -> Self {
+        Self {
+            column: Some(column),
+            row: Some(row),
+            ..Default::default()
+        }
+    }
+
+    pub fn in_area(area: &str) -> Self {
+        Self {
+            area: Some(area.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+// A placement along the axis auto-placement fills (primary) and the axis it grows (secondary)
+#[derive(Copy, Clone, Default)]
+struct Placement {
+    primary: Option<usize>,
+    secondary: Option<usize>,
+    primary_span: usize,
+    secondary_span: usize,
+}
+
+// Cells in use, indexed by secondary then primary track
+struct Occupancy {
+    primary_count: usize,
+    cells: Vec<Vec<bool>>,
+}
+
+impl Occupancy {
+    fn fits(&self, primary: usize, secondary: usize, placement: &Placement) -> bool {
+        if primary + placement.primary_span > self.primary_count {
+            return false;
+        }
+        (secondary..secondary + placement.secondary_span).all(|s| {
+            self.cells
+                .get(s)
+                .is_none_or(|row| !row[primary..primary + placement.primary_span].contains(&true))
+        })
+    }
+
+    // Add implicit tracks so there are at least `primary_count`
+    fn grow(&mut self, primary_count: usize) {
+        if primary_count > self.primary_count {
+            self.primary_count = primary_count;
+            for row in &mut self.cells {
+                row.resize(primary_count, false);
+            }
+        }
+    }
+
+    fn fill(&mut self, primary: usize, secondary: usize, placement: &Placement) {
+        let end = secondary + placement.secondary_span;
+        if self.cells.len() < end {
+            self.cells.resize(end, vec![false; self.primary_count]);
+        }
+        for row in &mut self.cells[secondary..end] {
+            row[primary..primary + placement.primary_span].fill(true);
+        }
+    }
+}
+
+/// Returns the margin box of each item, relative to the container's content box
+///
+pub(crate) fn layout(container: &GridContainer, content: Size, items: &[LayoutItem]) -> Vec<Rect> {
+    let areas = parse_areas(&container.areas);
+    let column_flow = container.auto_flow == GridAutoFlow::Column;
+
+    // (column, row, column_span, row_span) with every position resolved
+    let cells = place_items(container, &areas, items, column_flow);
+
+    let column_count = cells
+        .iter()
+        .map(|c| c.0 + c.2)
+        .max()
+        .unwrap_or(0)
+        .max(container.columns.len());
+    let row_count = cells
+        .iter()
+        .map(|c| c.1 + c.3)
+        .max()
+        .unwrap_or(0)
+        .max(container.rows.len());
+
+    let columns = size_tracks(
+        &container.columns,
+        container.auto_tracks,
+        column_count,
+        content.width,
+        container.column_gap,
+        cells
+            .iter()
+            .zip(items)
+            .map(|(c, item)| (c.0, c.2, item.size.width + item.margin.horizontal())),
+    );
+    let rows = size_tracks(
+        &container.rows,
+        container.auto_tracks,
+        row_count,
+        content.height,
+        container.row_gap,
+        cells
+            .iter()
+            .zip(items)
+            .map(|(c, item)| (c.1, c.3, item.size.height + item.margin.vertical())),
+    );
+
+    // items fill their area as far as their limits allow, from its top left
+    cells
+        .iter()
+        .zip(items)
+        .map(|(&(column, row, column_span, row_span), item)| {
+            let (x, width) = columns.span(column, column_span);
+            let (y, height) = rows.span(row, row_span);
+            let margin = Size::from_wh(item.margin.horizontal(), item.margin.vertical());
+            let size = item.constraints.fit(Size::from_wh(width, height) - margin) + margin;
+            Rect::from_values(x, y, size.width, size.height)
+        })
+        .collect()
+}
+
+// Cell bounds of each named area as (column, row, column_span, row_span)
+fn parse_areas(areas: &[String]) -> HashMap<&str, (usize, usize, usize, usize)> {
+    let mut bounds: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (row, line) in areas.iter().enumerate() {
+        for (column, name) in line.split_whitespace().enumerate() {
+            if name == "." {
+                continue;
+            }
+            let b = bounds.entry(name).or_insert((column, row, column, row));
+            *b = (b.0.min(column), b.1.min(row), b.2.max(column), b.3.max(row));
+        }
+    }
+    bounds
+        .into_iter()
+        .map(|(name, (c0, r0, c1, r1))| (name, (c0, r0, c1 - c0 + 1, r1 - r0 + 1)))
+        .collect()
+}
+
+// Resolve every item to a grid position. Items with a full position go first, then items with
+// only the secondary track set, then the rest in order with a cursor that only moves forward.
+fn place_items(
+    container: &GridContainer,
+    areas: &HashMap<&str, (usize, usize, usize, usize)>,
+    items: &[LayoutItem],
+    column_flow: bool,
+) -> Vec<(usize, usize, usize, usize)> {
+    let placements: Vec<Placement> = items
+        .iter()
+        .map(|item| {
+            let grid = &item.style.grid;
+            let (column, row, column_span, row_span) =
+                match grid.area.as_deref().and_then(|name| areas.get(name)) {
+                    Some(&(c, r, cs, rs)) => (Some(c), Some(r), cs, rs),
+                    None => (
+                        grid.column,
+                        grid.row,
+                        grid.column_span.max(1),
+                        grid.row_span.max(1),
+                    ),
+                };
+            if column_flow {
+                Placement {
+                    primary: row,
+                    secondary: column,
+                    primary_span: row_span,
+                    secondary_span: column_span,
+                }
+            } else {
+                Placement {
+                    primary: column,
+                    secondary: row,
+                    primary_span: column_span,
+                    secondary_span: row_span,
+                }
+            }
+        })
+        .collect();
+
+    let explicit_primary = if column_flow {
+        container.rows.len().max(container.areas.len())
+    } else {
+        let area_columns = container
+            .areas
+            .iter()
+            .map(|line| line.split_whitespace().count())
+            .max()
+            .unwrap_or(0);
+        container.columns.len().max(area_columns)
+    };
+    let primary_count = placements
+        .iter()
+        .map(|p| p.primary.unwrap_or(0) + p.primary_span)
+        .max()
+        .unwrap_or(0)
+        .max(explicit_primary)
+        .max(1);
+
+    let mut occupancy = Occupancy {
+        primary_count,
+        cells: Vec::new(),
+    };
+    let mut resolved: Vec<Option<(usize, usize)>> = vec![None; placements.len()];
+
+    for (i, p) in placements.iter().enumerate() {
+        if let (Some(primary), Some(secondary)) = (p.primary, p.secondary) {
+            occupancy.fill(primary, secondary, p);
+            resolved[i] = Some((primary, secondary));
+        }
+    }
+
+    for (i, p) in placements.iter().enumerate() {
+        if let (None, Some(secondary)) = (p.primary, p.secondary) {
+            // the line grows past the explicit tracks when the item fits nowhere before them
+            let primary = (0..)
+                .find(|&primary| {
+                    occupancy.grow(primary + p.primary_span);
+                    occupancy.fits(primary, secondary, p)
+                })
+                .unwrap_or_default();
+            occupancy.fill(primary, secondary, p);
+            resolved[i] = Some((primary, secondary));
+        }
+    }
+
+    let (mut cursor_primary, mut cursor_secondary) = (0, 0);
+    for (i, p) in placements.iter().enumerate() {
+        if resolved[i].is_some() {
+            continue;
+        }
+        match p.primary {
+            Some(primary) => {
+                if primary < cursor_primary {
+                    cursor_secondary += 1;
+                }
+                while !occupancy.fits(primary, cursor_secondary, p) {
+                    cursor_secondary += 1;
+                }
+                cursor_primary = primary;
+            }
+            None => {
+                while !occupancy.fits(cursor_primary, cursor_secondary, p) {
+                    cursor_primary += 1;
+                    if cursor_primary + p.primary_span > occupancy.primary_count {
+                        cursor_primary = 0;
+                        cursor_secondary += 1;
+                    }
+                }
+            }
+        }
+        occupancy.fill(cursor_primary, cursor_secondary, p);
+        resolved[i] = Some((cursor_primary, cursor_secondary));
+        cursor_primary += p.primary_span;
+    }
+
+    placements
+        .iter()
+        .zip(resolved)
+        .map(|(p, position)| {
+            let (primary, secondary) = position.unwrap_or_default();
+            if column_flow {
+                (secondary, primary, p.secondary_span, p.primary_span)
+            } else {
+                (primary, secondary, p.primary_span, p.secondary_span)
+            }
+        })
+        .collect()
+}
+
+// Start and size of each track along one axis
+struct Tracks {
+    starts: Vec<Scalar>,
+    sizes: Vec<Scalar>,
+}
+
+impl Tracks {
+    fn span(&self, start: usize, span: usize) -> (Scalar, Scalar) {
+        let last = start + span - 1;
+        (
+            self.starts[start],
+            self.starts[last] + self.sizes[last] - self.starts[start],
+        )
+    }
+}
+
+// `items` are (first track, span, outer size) along this axis
+fn size_tracks(
+    explicit: &[TrackSize],
+    auto_tracks: TrackSize,
+    count: usize,
+    available: Scalar,
+    gap: Scalar,
+    items: impl Iterator<Item = (usize, usize, Scalar)> + Clone,
+) -> Tracks {
+    let definitions: Vec<TrackSize> = (0..count)
+        .map(|i| explicit.get(i).copied().unwrap_or(auto_tracks))
+        .collect();
+    let mut sizes: Vec<Scalar> = definitions
+        .iter()
+        .map(|d| match d {
+            TrackSize::Fixed(size) => *size,
+            _ => 0.0,
+        })
+        .collect();
+
+    // auto tracks grow to fit the items that sit in only one track, then spanning items share
+    // what they still need between the auto tracks they cross
+    for (start, span, size) in items.clone() {
+        if span == 1 && definitions[start] == TrackSize::Auto {
+            sizes[start] = sizes[start].max(size);
+        }
+    }
+    for (start, span, size) in items {
+        let range = start..start + span;
+        let crosses_fr = definitions[range.clone()]
+            .iter()
+            .any(|d| matches!(d, TrackSize::Fr(_)));
+        let autos: Vec<usize> = range
+            .clone()
+            .filter(|i| definitions[*i] == TrackSize::Auto)
+            .collect();
+        if span == 1 || crosses_fr || autos.is_empty() {
+            continue;
+        }
+        let current = sizes[range].iter().sum::<Scalar>() + gap * (span - 1) as Scalar;
+        if size > current {
+            let extra = (size - current) / autos.len() as Scalar;
+            for i in autos {
+                sizes[i] += extra;
+            }
+        }
+    }
+
+    let gaps = gap * (count.max(1) - 1) as Scalar;
+    let free = Scalar::max(available - sizes.iter().sum::<Scalar>() - gaps, 0.0);
+    let fr_total: Scalar = definitions
+        .iter()
+        .map(|d| match d {
+            TrackSize::Fr(fr) => *fr,
+            _ => 0.0,
+        })
+        .sum();
+    if fr_total > 0.0 {
+        for (size, d) in sizes.iter_mut().zip(&definitions) {
+            if let TrackSize::Fr(fr) = d {
+                *size = free * fr / fr_total;
+            }
+        }
+    } else {
+        let auto_count = definitions
+            .iter()
+            .filter(|d| **d == TrackSize::Auto)
+            .count();
+        if auto_count > 0 {
+            let extra = free / auto_count as Scalar;
+            for (size, d) in sizes.iter_mut().zip(&definitions) {
+                if *d == TrackSize::Auto {
+                    *size += extra;
+                }
+            }
+        }
+    }
+
+    let mut starts = Vec::with_capacity(count);
+    let mut position = 0.0;
+    for size in &sizes {
+        starts.push(position);
+        position += size + gap;
+    }
+    Tracks { starts, sizes }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constraints::Constraints;
+    use crate::grid::*;
+    use crate::{ItemStyle, LayoutItem};
+    use clui_base::Padding;
+
+    fn styled(grid: GridItem) -> ItemStyle {
+        ItemStyle {
+            grid,
+            ..Default::default()
+        }
+    }
+
+    fn item(style: &ItemStyle, width: Scalar, height: Scalar) -> LayoutItem<'_> {
+        LayoutItem {
+            margin: Padding::default(),
+            size: Size::from_wh(width, height),
+            constraints: Constraints::default(),
+            style,
+        }
+    }
+
+    #[test]
+    fn fixed_fr_and_auto_tracks() {
+        let container = GridContainer {
+            columns: vec![TrackSize::Fixed(50.0), TrackSize::Auto, TrackSize::Fr(1.0)],
+            rows: vec![TrackSize::Fr(1.0), TrackSize::Fr(3.0)],
+            column_gap: 10.0,
+            ..Default::default()
+        };
+        let s = ItemStyle::default();
+        let rects = layout(
+            &container,
+            Size::from_wh(300.0, 100.0),
+            &[
+                item(&s, 0.0, 0.0),
+                item(&s, 70.0, 0.0),
+                item(&s, 0.0, 0.0),
+                item(&s, 0.0, 0.0),
+            ],
+        );
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 50.0, 25.0));
+        assert_eq!(rects[1], Rect::from_values(60.0, 0.0, 70.0, 25.0));
+        assert_eq!(rects[2], Rect::from_values(140.0, 0.0, 160.0, 25.0));
+        assert_eq!(rects[3], Rect::from_values(0.0, 25.0, 50.0, 75.0));
+    }
+
+    #[test]
+    fn auto_placement_with_spans() {
+        let container = GridContainer {
+            columns: vec![TrackSize::Fixed(10.0); 3],
+            auto_tracks: TrackSize::Fixed(10.0),
+            ..Default::default()
+        };
+        let wide = styled(GridItem {
+            column_span: 2,
+            ..Default::default()
+        });
+        let pinned = styled(GridItem::at(2, 0));
+        let second_column = styled(GridItem {
+            column: Some(1),
+            ..Default::default()
+        });
+        let in_row = styled(GridItem {
+            row: Some(3),
+            ..Default::default()
+        });
+        let s = ItemStyle::default();
+        let rects = layout(
+            &container,
+            Size::from_wh(30.0, 100.0),
+            &[
+                item(&wide, 0.0, 0.0),
+                item(&s, 0.0, 0.0),
+                item(&pinned, 0.0, 0.0),
+                item(&wide, 0.0, 0.0),
+                item(&second_column, 0.0, 0.0),
+                item(&in_row, 0.0, 0.0),
+            ],
+        );
+        // the pinned item takes (2, 0) first, so the single cell item moves to the next row
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 20.0, 10.0));
+        assert_eq!(rects[2], Rect::from_values(20.0, 0.0, 10.0, 10.0));
+        assert_eq!(rects[1], Rect::from_values(0.0, 10.0, 10.0, 10.0));
+        assert_eq!(rects[3], Rect::from_values(10.0, 10.0, 20.0, 10.0));
+        // a locked column behind the cursor starts a new row
+        assert_eq!(rects[4], Rect::from_values(10.0, 20.0, 10.0, 10.0));
+        // locked rows are placed before the cursor runs
+        assert_eq!(rects[5], Rect::from_values(0.0, 30.0, 10.0, 10.0));
+    }
+
+    #[test]
+    fn pinned_row_grows_implicit_columns() {
+        let container = GridContainer {
+            columns: vec![TrackSize::Fixed(10.0); 2],
+            auto_tracks: TrackSize::Fixed(10.0),
+            ..Default::default()
+        };
+        let in_row = styled(GridItem {
+            row: Some(0),
+            ..Default::default()
+        });
+        let wide_in_row = styled(GridItem {
+            row: Some(0),
+            column_span: 2,
+            ..Default::default()
+        });
+        let rects = layout(
+            &container,
+            Size::from_wh(20.0, 100.0),
+            &[
+                item(&wide_in_row, 0.0, 0.0),
+                item(&in_row, 0.0, 0.0),
+                item(&wide_in_row, 0.0, 0.0),
+            ],
+        );
+        // the row is full, so the later items go into implicit columns rather than on top
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 20.0, 10.0));
+        assert_eq!(rects[1], Rect::from_values(20.0, 0.0, 10.0, 10.0));
+        assert_eq!(rects[2], Rect::from_values(30.0, 0.0, 20.0, 10.0));
+    }
+
+    #[test]
+    fn items_kept_within_limits() {
+        let container = GridContainer {
+            columns: vec![TrackSize::Fixed(100.0); 2],
+            rows: vec![TrackSize::Fixed(50.0)],
+            ..Default::default()
+        };
+        let s = ItemStyle::default();
+        let mut thumbnail = item(&s, 0.0, 0.0);
+        thumbnail.constraints.aspect_ratio = Some(0.5);
+        let mut capped = item(&s, 0.0, 0.0);
+        capped.constraints.max.width = 60.0;
+        capped.margin = Padding::all(5.0);
+        let rects = layout(&container, Size::from_wh(200.0, 50.0), &[thumbnail, capped]);
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 25.0, 50.0));
+        assert_eq!(rects[1], Rect::from_values(100.0, 0.0, 70.0, 50.0));
+    }
+
+    #[test]
+    fn named_areas() {
+        let container = GridContainer {
+            columns: vec![TrackSize::Fixed(40.0), TrackSize::Fr(1.0)],
+            rows: vec![TrackSize::Auto, TrackSize::Fr(1.0)],
+            areas: vec!["header header".to_string(), "side main".to_string()],
+            row_gap: 4.0,
+            column_gap: 4.0,
+            ..Default::default()
+        };
+        let header = styled(GridItem::in_area("header"));
+        let side = styled(GridItem::in_area("side"));
+        let main = styled(GridItem::in_area("main"));
+        let rects = layout(
+            &container,
+            Size::from_wh(200.0, 100.0),
+            &[
+                item(&main, 0.0, 0.0),
+                item(&header, 0.0, 16.0),
+                item(&side, 0.0, 0.0),
+            ],
+        );
+        assert_eq!(rects[1], Rect::from_values(0.0, 0.0, 200.0, 16.0));
+        assert_eq!(rects[2], Rect::from_values(0.0, 20.0, 40.0, 80.0));
+        assert_eq!(rects[0], Rect::from_values(44.0, 20.0, 156.0, 80.0));
+    }
+
+    #[test]
+    fn column_flow_and_spanning_auto_tracks() {
+        let container = GridContainer {
+            rows: vec![TrackSize::Auto, TrackSize::Auto],
+            auto_flow: GridAutoFlow::Column,
+            ..Default::default()
+        };
+        let tall = styled(GridItem {
+            row_span: 2,
+            ..Default::default()
+        });
+        let s = ItemStyle::default();
+        let rects = layout(
+            &container,
+            Size::from_wh(0.0, 0.0),
+            &[
+                item(&s, 10.0, 10.0),
+                item(&s, 20.0, 10.0),
+                item(&tall, 5.0, 40.0),
+            ],
+        );
+        // down the first column, then into the second; the tall item needs 20 more than the
+        // two rows give it, shared between them
+        assert_eq!(rects[0], Rect::from_values(0.0, 0.0, 20.0, 20.0));
+        assert_eq!(rects[1], Rect::from_values(0.0, 20.0, 20.0, 20.0));
+        assert_eq!(rects[2], Rect::from_values(20.0, 0.0, 5.0, 40.0));
+    }
+}
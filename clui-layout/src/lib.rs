@@ -1,8 +1,652 @@
+pub use crate::anchor::AnchorItem;
+pub use crate::flex::{
+    AlignContent, AlignItems, FlexContainer, FlexDirection, FlexItem, FlexWrap, JustifyContent,
+};
+pub use crate::grid::{GridAutoFlow, GridContainer, GridItem, TrackSize};
+
+use crate::constraints::Constraints;
+use clui_base::{
+    Clui, CluiError, CluiLayer, CluiLayerKey, CluiUiRect, CluiWindowKey, Fonts, LengthContext,
+    Padding, Point, Rect, Scalar, Size,
+};
+use slotmap::SecondaryMap;
+
+mod anchor;
+mod constraints;
+mod flex;
+mod grid;
+
+/// How a window arranges its children
+///
+#[derive(Clone, PartialEq, Debug)]
+pub enum Layout {
+    Flex(FlexContainer),
+    Grid(GridContainer),
+    /// Each child is placed by its `AnchorItem`
+    Anchor,
+}
+
+/// Measures the content of a window, given the width available to it, for windows whose size
+/// depends on what they hold
+///
+pub type MeasureFn = dyn Fn(&Fonts, Option<Scalar>) -> Size;
+
+/// Per child settings, read by whichever layout the parent uses
+///
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ItemStyle {
+    pub flex: FlexItem,
+    pub grid: GridItem,
+    pub anchor: AnchorItem,
+}
+
+/// Layout settings for the windows of a layer. Each call to `apply` walks the window tree from
+/// the roots down, so a container's rect is final before its children are arranged inside its
+/// content box. Children that are out of flow are left where they are.
+///
+/// Every layout keeps children within the size limits and aspect ratio of their box model.
+/// Children whose limits contradict each other are reported through the log handler.
+///
+/// Box model lengths are resolved as the layer resolves them, against the parent's content box,
+/// or the layer viewport for top level windows. Only rects are written: windows that are not
+/// placed by a parent's layout take their size on each axis with a content size from the box
+/// model.
+///
+/// Children with a measure function, or with text, are sized to their content in the width the
+/// parent's content box leaves them. A content size set in the box model takes precedence.
+///
+pub struct LayoutEngine {
+    layouts: SecondaryMap<CluiWindowKey, Layout>,
+    item_styles: SecondaryMap<CluiWindowKey, ItemStyle>,
+    measures: SecondaryMap<CluiWindowKey, Box<MeasureFn>>,
+    // the last problem logged for each window, so it is only logged again if it changes
+    reported: SecondaryMap<CluiWindowKey, String>,
+}
+
+// What a layout needs to know about a child
+pub(crate) struct LayoutItem<'a> {
+    pub margin: Padding,
+    // intrinsic border box size, within the constraints
+    pub size: Size,
+    pub constraints: Constraints,
+    pub style: &'a ItemStyle,
+}
+
+impl Default for LayoutEngine {
+    fn default() -> Self {
+        Self {
+            layouts: SecondaryMap::new(),
+            item_styles: SecondaryMap::new(),
+            measures: SecondaryMap::new(),
+            reported: SecondaryMap::new(),
+        }
+    }
+}
+
+impl LayoutEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_layout(&mut self, key: CluiWindowKey, layout: Layout) {
+        self.layouts.insert(key, layout);
+    }
+
+    pub fn layout(&self, key: CluiWindowKey) -> Option<&Layout> {
+        self.layouts.get(key)
+    }
+
+    pub fn set_item_style(&mut self, key: CluiWindowKey, style: ItemStyle) {
+        self.item_styles.insert(key, style);
+    }
+
+    /// The style of a child, inserting the default if it has none. Returns `None` if the key
+    /// is stale.
+    ///
+    pub fn item_style_mut(&mut self, key: CluiWindowKey) -> Option<&mut ItemStyle> {
+        Some(self.item_styles.entry(key)?.or_default())
+    }
+
+    /// Size a window by its content rather than its box model's content size. Windows with
+    /// text are measured by their text unless they have a measure function.
+    ///
+    pub fn set_measure<F>(&mut self, key: CluiWindowKey, measure: F)
+    where
+        F: Fn(&Fonts, Option<Scalar>) -> Size + 'static,
+    {
+        self.measures.insert(key, Box::new(measure));
+    }
+
+    /// Forget the layout, styles and measure function of a window
+    ///
+    pub fn remove(&mut self, key: CluiWindowKey) {
+        self.layouts.remove(key);
+        self.item_styles.remove(key);
+        self.measures.remove(key);
+    }
+
+    /// Arrange every window in a layer. Rects are only written back when they change, so
+    /// the layer rebuilds no more than it has to on the next update.
+    ///
+    pub fn apply(&mut self, clui: &mut Clui, layer: CluiLayerKey) -> Result<(), CluiError> {
+        let (layer, fonts) = clui
+            .get_layer_and_fonts(layer)
+            .ok_or(CluiError::InvalidLayerKey)?;
+        for message in self.apply_to_layer(layer, fonts) {
+            clui.log(&message);
+        }
+        Ok(())
+    }
+
+    // Returns the problems found that have not been reported before
+    pub(crate) fn apply_to_layer(&mut self, layer: &mut CluiLayer, fonts: &Fonts) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        let context = layer.root_length_context();
+        let roots = layer.root_windows().to_vec();
+        for root in roots {
+            let context = Self::size_from_box_model(layer, root, &context, false);
+            self.arrange(layer, fonts, root, &context, &mut conflicts);
+        }
+
+        let mut messages = Vec::new();
+        let mut reported = SecondaryMap::new();
+        for (key, conflict) in conflicts {
+            let message = format!(
+                "clui-layout: window {:?} is overconstrained, {}",
+                key, conflict
+            );
+            if self.reported.get(key) != Some(&message) {
+                messages.push(message.clone());
+            }
+            reported.insert(key, message);
+        }
+        self.reported = reported;
+        messages
+    }
+
+    // Resize a window to the content size of its box model, unless a layout places it,
+    // returning the context its own lengths are resolved in
+    fn size_from_box_model(
+        layer: &mut CluiLayer,
+        key: CluiWindowKey,
+        parent: &LengthContext,
+        placed_by_layout: bool,
+    ) -> LengthContext {
+        let window = layer.get_window_by_key_unchecked(key);
+        let model = window.box_model();
+        let context = model.length_context(parent);
+        if placed_by_layout || (model.content_width.is_none() && model.content_height.is_none()) {
+            return context;
+        }
+
+        let outer = model.outer_size(&context);
+        let mut rect = window.rect();
+        if model.content_width.is_some() {
+            rect.size.width = outer.width;
+        }
+        if model.content_height.is_some() {
+            rect.size.height = outer.height;
+        }
+        if rect != window.rect() {
+            layer.get_mut_window_by_key_unchecked(key).set_rect(rect);
+        }
+        context
+    }
+
+    // The border box size of a child, measured if it has a measure function or text
+    fn content_size(
+        &self,
+        window: &CluiUiRect,
+        key: CluiWindowKey,
+        context: &LengthContext,
+        fonts: &Fonts,
+        available: Scalar,
+    ) -> Size {
+        let model = window.box_model();
+        let border_size = model.border_size(context);
+        let frame = model.frame_size(context);
+        let fixed_width = model.content_width.is_some();
+        let fixed_height = model.content_height.is_some();
+        let available = if fixed_width {
+            border_size.width - frame.width
+        } else {
+            (available - frame.width).max(0.0)
+        };
+
+        let measured = match self.measures.get(key) {
+            Some(measure) => measure(fonts, Some(available)),
+            None => match window.text() {
+                Some(text) => text.measure(fonts, Some(available)),
+                None => return border_size,
+            },
+        };
+        Size::from_wh(
+            if fixed_width {
+                border_size.width
+            } else {
+                measured.width + frame.width
+            },
+            if fixed_height {
+                border_size.height
+            } else {
+                measured.height + frame.height
+            },
+        )
+    }
+
+    fn arrange(
+        &self,
+        layer: &mut CluiLayer,
+        fonts: &Fonts,
+        key: CluiWindowKey,
+        context: &LengthContext,
+        conflicts: &mut Vec<(CluiWindowKey, String)>,
+    ) {
+        let children = layer.children(key).to_vec();
+        let layout = self.layouts.get(key);
+
+        let window = layer.get_window_by_key_unchecked(key);
+        let content = window
+            .box_model()
+            .resolve(
+                &Rect::from_pos_and_size(Point::default(), window.rect().size),
+                context,
+            )
+            .content
+            .size;
+        let parent = LengthContext {
+            parent_content: content,
+            ..*context
+        };
+        let child_contexts: Vec<LengthContext> = children
+            .iter()
+            .map(|child| {
+                let placed_by_layout =
+                    layout.is_some() && !layer.get_window_by_key_unchecked(*child).is_out_of_flow();
+                Self::size_from_box_model(layer, *child, &parent, placed_by_layout)
+            })
+            .collect();
+
+        if let Some(layout) = layout {
+            let default_style = ItemStyle::default();
+            let in_flow: Vec<(CluiWindowKey, &LengthContext)> = children
+                .iter()
+                .copied()
+                .zip(&child_contexts)
+                .filter(|(k, _)| !layer.get_window_by_key_unchecked(*k).is_out_of_flow())
+                .collect();
+            let items: Vec<LayoutItem> = in_flow
+                .iter()
+                .map(|(k, context)| {
+                    let window = layer.get_window_by_key_unchecked(*k);
+                    let model = window.box_model();
+                    let constraints = Constraints::from_box_model(model, context);
+                    if let Some(conflict) = constraints.conflict() {
+                        conflicts.push((*k, conflict));
+                    }
+                    let margin = model.margin.fixed(context);
+                    let available = content.width - margin.horizontal();
+                    let size = self.content_size(window, *k, context, fonts, available);
+                    LayoutItem {
+                        margin,
+                        size: constraints.intrinsic(size),
+                        constraints,
+                        style: self.item_styles.get(*k).unwrap_or(&default_style),
+                    }
+                })
+                .collect();
+
+            let rects = match layout {
+                Layout::Flex(container) => flex::layout(container, content, &items),
+                Layout::Grid(container) => grid::layout(container, content, &items),
+                Layout::Anchor => anchor::layout(content, &items),
+            };
+
+            for ((k, _), rect) in in_flow.iter().zip(rects) {
+                if layer.get_window_by_key_unchecked(*k).rect() != rect {
+                    layer.get_mut_window_by_key_unchecked(*k).set_rect(rect);
+                }
+            }
+        }
+
+        for (child, context) in children.iter().zip(&child_contexts) {
+            self.arrange(layer, fonts, *child, context, conflicts);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::{
+        AlignItems, FlexContainer, FlexDirection, FlexItem, ItemStyle, Layout, LayoutEngine,
+    };
+    use clui_base::{
+        BoxModel, Clui, CluiError, CluiPositioning, CluiText, CluiUiRect, Length, Margin,
+        MarginDef, Padding, Rect, Size, TextStyle,
+    };
+
+    #[test]
+    fn nested_layouts_write_rects() {
+        let mut clui = Clui::new();
+        let layer_key = clui.create_layer();
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+
+        let root = layer.add_window(CluiUiRect::with_box_model(
+            Rect::from_values(0.0, 0.0, 220.0, 100.0),
+            BoxModel {
+                padding: Padding::all(10.0),
+                ..Default::default()
+            },
+        ));
+        let left = layer.add_child_window(root, CluiUiRect::default()).unwrap();
+        let right = layer.add_child_window(root, CluiUiRect::default()).unwrap();
+        let inner = layer
+            .add_child_window(
+                right,
+                CluiUiRect::with_box_model(
+                    Rect::default(),
+                    BoxModel {
+                        margin: Margin::all(MarginDef::Value(Length::Px(5.0))),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        let overlay = layer.add_child_window(root, CluiUiRect::default()).unwrap();
+        {
+            let mut w = layer.get_mut_window_by_key_unchecked(overlay);
+            w.set_positioning(CluiPositioning::Absolute);
+            w.set_rect(Rect::from_values(1.0, 2.0, 3.0, 4.0));
+        }
+
+        let mut engine = LayoutEngine::new();
+        engine.set_layout(root, Layout::Flex(FlexContainer::default()));
+        engine.set_layout(right, Layout::Flex(FlexContainer::default()));
+        engine.item_style_mut(left).unwrap().flex.grow = 1.0;
+        engine.set_item_style(
+            right,
+            ItemStyle {
+                flex: FlexItem {
+                    grow: 3.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        engine.item_style_mut(inner).unwrap().flex.grow = 1.0;
+        assert_eq!(engine.apply(&mut clui, layer_key), Ok(()));
+
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        assert_eq!(
+            layer.get_window_by_key_unchecked(left).rect(),
+            Rect::from_values(0.0, 0.0, 50.0, 80.0)
+        );
+        assert_eq!(
+            layer.get_window_by_key_unchecked(right).rect(),
+            Rect::from_values(50.0, 0.0, 150.0, 80.0)
+        );
+        // margins are part of the rect, the box model insets them
+        assert_eq!(
+            layer.get_window_by_key_unchecked(inner).rect(),
+            Rect::from_values(0.0, 0.0, 150.0, 80.0)
+        );
+        assert_eq!(
+            layer.get_window_by_key_unchecked(overlay).rect(),
+            Rect::from_values(1.0, 2.0, 3.0, 4.0)
+        );
+
+        layer.update();
+        assert_eq!(
+            layer.box_rects(inner).unwrap().border,
+            Rect::from_values(65.0, 15.0, 140.0, 70.0)
+        );
+
+        // a window added in the slot of a removed one leaves its key stale
+        layer.remove_window(inner);
+        let replacement = layer
+            .add_child_window(right, CluiUiRect::default())
+            .unwrap();
+        engine.set_item_style(replacement, ItemStyle::default());
+        assert!(engine.item_style_mut(inner).is_none());
+        assert!(engine.item_style_mut(left).is_some());
+    }
+
+    #[test]
+    fn overconstrained_windows_logged_once() {
+        let mut messages = Vec::new();
+        {
+            let mut clui = Clui::new();
+            clui.set_log_message_handler(|message| {
+                messages.push(message.to_string());
+                true
+            });
+            let layer_key = clui.create_layer();
+            let layer = clui.get_layer_by_key(layer_key).unwrap();
+            let root = layer.add_window(CluiUiRect::new(
+                Rect::from_values(0.0, 0.0, 100.0, 100.0),
+                Default::default(),
+            ));
+            let child = layer
+                .add_child_window(
+                    root,
+                    CluiUiRect::with_box_model(
+                        Rect::default(),
+                        BoxModel {
+                            min_width: Length::Px(50.0),
+                            max_width: Some(Length::Px(20.0)),
+                            ..Default::default()
+                        },
+                    ),
+                )
+                .unwrap();
+
+            let mut engine = LayoutEngine::new();
+            engine.set_layout(root, Layout::Flex(FlexContainer::default()));
+            engine.apply(&mut clui, layer_key).unwrap();
+            engine.apply(&mut clui, layer_key).unwrap();
+
+            // the minimum wins, and the size is never negative
+            let layer = clui.get_layer_by_key(layer_key).unwrap();
+            assert_eq!(
+                layer.get_window_by_key_unchecked(child).rect(),
+                Rect::from_values(0.0, 0.0, 50.0, 100.0)
+            );
+
+            // fixing the problem and breaking it again logs again
+            layer
+                .get_mut_window_by_key_unchecked(child)
+                .box_model_mut()
+                .max_width = None;
+            engine.apply(&mut clui, layer_key).unwrap();
+            clui.get_layer_by_key(layer_key)
+                .unwrap()
+                .get_mut_window_by_key_unchecked(child)
+                .box_model_mut()
+                .max_width = Some(Length::Px(20.0));
+            engine.apply(&mut clui, layer_key).unwrap();
+        }
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("max_width 20"));
+    }
+
+    #[test]
+    fn box_model_lengths_resolved() {
+        let mut clui = Clui::new();
+        let layer_key = clui.create_layer();
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        layer.update_viewport(1000.0, 500.0);
+        layer.set_root_font_size(10.0);
+        let root = layer.add_window(CluiUiRect::with_box_model(
+            Rect::default(),
+            BoxModel {
+                content_width: Some(Length::Vw(48.0)),
+                content_height: Some(Length::Vh(96.0)),
+                padding: Padding::all(Length::Rem(1.0)),
+                ..Default::default()
+            },
+        ));
+        let sidebar_model = BoxModel {
+            content_width: Some(Length::Percent(40.0)),
+            font_size: Some(Length::Em(2.0)),
+            padding: Padding::all(Length::Em(0.5)),
+            ..Default::default()
+        };
+        let sidebar = layer
+            .add_child_window(
+                root,
+                CluiUiRect::with_box_model(Rect::default(), sidebar_model),
+            )
+            .unwrap();
+        let label = layer
+            .add_child_window(
+                sidebar,
+                CluiUiRect::with_box_model(
+                    Rect::default(),
+                    BoxModel {
+                        content_width: Some(Length::Em(3.0)),
+                        content_height: Some(Length::Percent(50.0)),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+
+        let mut engine = LayoutEngine::new();
+        engine.set_layout(root, Layout::Flex(FlexContainer::default()));
+        engine.apply(&mut clui, layer_key).unwrap();
+
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        assert_eq!(
+            layer.get_window_by_key_unchecked(root).rect(),
+            Rect::from_values(0.0, 0.0, 500.0, 500.0)
+        );
+        // 40% of the 480 content width plus padding, stretched to the content height
+        assert_eq!(
+            layer.get_window_by_key_unchecked(sidebar).rect(),
+            Rect::from_values(0.0, 0.0, 212.0, 480.0)
+        );
+        // only rects are written, the box model keeps its lengths
+        assert_eq!(
+            *layer.get_window_by_key_unchecked(sidebar).box_model(),
+            sidebar_model
+        );
+        // em follows the sidebar's font size, the sidebar has no layout so the size is set here
+        assert_eq!(
+            layer.get_window_by_key_unchecked(label).rect(),
+            Rect::from_values(0.0, 0.0, 60.0, 230.0)
+        );
+
+        // the layer resolves the same lengths
+        layer.update();
+        assert_eq!(
+            layer.box_rects(sidebar).unwrap().content,
+            Rect::from_values(20.0, 20.0, 192.0, 460.0)
+        );
+
+        // resizing the viewport carries through on the next apply
+        layer.update_viewport(2000.0, 500.0);
+        engine.apply(&mut clui, layer_key).unwrap();
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        assert_eq!(
+            layer.get_window_by_key_unchecked(sidebar).rect().width(),
+            404.0
+        );
+    }
+
+    #[test]
+    fn content_sized_windows() {
+        let mut clui = Clui::new();
+        clui.set_file_read_handler(|path| std::fs::read(path).ok());
+        let font = clui
+            .load_font(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../clui-base/tests/fixtures/fonts/DejaVuSans.ttf"
+            ))
+            .unwrap();
+        let layer_key = clui.create_layer();
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        let root = layer.add_window(CluiUiRect::new(
+            Rect::from_values(0.0, 0.0, 300.0, 200.0),
+            Default::default(),
+        ));
+        let mut label = CluiUiRect::with_box_model(
+            Rect::default(),
+            BoxModel {
+                padding: Padding::all(4.0),
+                ..Default::default()
+            },
+        );
+        label.set_text(Some(CluiText::new(
+            "a few words of text",
+            TextStyle {
+                font,
+                size: 20.0,
+                ..Default::default()
+            },
+        )));
+        let label = layer.add_child_window(root, label).unwrap();
+        let icon = layer.add_child_window(root, CluiUiRect::default()).unwrap();
+
+        let mut engine = LayoutEngine::new();
+        engine.set_layout(
+            root,
+            Layout::Flex(FlexContainer {
+                direction: FlexDirection::Column,
+                align_items: AlignItems::FlexStart,
+                ..Default::default()
+            }),
+        );
+        engine.set_measure(icon, |_, available| {
+            Size::from_wh(available.unwrap_or(0.0).min(24.0), 24.0)
+        });
+        engine.apply(&mut clui, layer_key).unwrap();
+
+        let (layer, fonts) = clui.get_layer_and_fonts(layer_key).unwrap();
+        let text = layer.get_window_by_key_unchecked(label).text().unwrap();
+        let one_line = text.measure(fonts, None);
+        assert_eq!(
+            layer.get_window_by_key_unchecked(label).rect(),
+            Rect::from_values(0.0, 0.0, one_line.width + 8.0, one_line.height + 8.0)
+        );
+        assert_eq!(
+            layer.get_window_by_key_unchecked(icon).rect(),
+            Rect::from_values(0.0, one_line.height + 8.0, 24.0, 24.0)
+        );
+
+        // a narrower parent wraps the text onto more lines
+        layer
+            .get_mut_window_by_key_unchecked(root)
+            .set_rect(Rect::from_values(0.0, 0.0, 100.0, 200.0));
+        engine.apply(&mut clui, layer_key).unwrap();
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        let rect = layer.get_window_by_key_unchecked(label).rect();
+        assert!(rect.width() <= 100.0);
+        assert!(rect.height() >= one_line.height * 2.0 + 8.0);
+
+        // a content width from the box model is kept, and the text wraps inside it
+        layer
+            .get_mut_window_by_key_unchecked(label)
+            .box_model_mut()
+            .content_width = Some(Length::Px(52.0));
+        engine.apply(&mut clui, layer_key).unwrap();
+        let layer = clui.get_layer_by_key(layer_key).unwrap();
+        assert_eq!(
+            layer.get_window_by_key_unchecked(label).rect().width(),
+            60.0
+        );
+    }
+
     #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+    fn invalid_layer() {
+        let mut clui = Clui::new();
+        let key = clui.create_layer();
+        let mut other = Clui::new();
+        other.create_layer();
+        let stale = other.create_layer();
+        assert_eq!(
+            LayoutEngine::new().apply(&mut clui, stale),
+            Err(CluiError::InvalidLayerKey)
+        );
+        assert_eq!(LayoutEngine::new().apply(&mut clui, key), Ok(()));
     }
 }